@@ -0,0 +1,83 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::process;
+use std::time::Instant;
+
+use crate::{apply_step, load_image, save_ppm, Loaded};
+
+// Steps timed when none are given, with their default parameters
+const STEPS: &[&str] = &[
+    "gray",
+    "gauss",
+    "sobel",
+    "laplace",
+    "canny",
+    "equalize",
+    "clahe",
+    "threshold",
+    "adaptive-gauss",
+    "saturation=1.2",
+    "levels",
+    "kuwahara",
+    "oil",
+    "nlmeans=0.1,1,3",
+    "scale=600,0",
+    "rotate=90",
+];
+
+// Times decoding, unpacking, every step on a fresh copy of the image and
+// saving, then prints the breakdown
+pub fn run(file: &str, steps: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut rows: Vec<(String, f64)> = Vec::new();
+
+    let start = Instant::now();
+    let loaded = load_image(file)?;
+    rows.push(("decode".to_string(), start.elapsed().as_secs_f64()));
+    let start = Instant::now();
+    let image = match loaded {
+        Loaded::Packed(packed) => packed.unpack(),
+        Loaded::Float(image) => image,
+    };
+    rows.push(("unpack".to_string(), start.elapsed().as_secs_f64()));
+
+    let steps: Vec<String> = if steps.is_empty() {
+        STEPS.iter().map(|step| step.to_string()).collect()
+    } else {
+        steps.to_vec()
+    };
+    let none: Vec<String> = Vec::new();
+    for step in &steps {
+        let mut copy = image.clone();
+        let start = Instant::now();
+        apply_step(&mut copy, step, &mut none.iter())?;
+        rows.push((step.clone(), start.elapsed().as_secs_f64()));
+    }
+
+    let temp = env::temp_dir().join(format!("ppm_filter_bench.{}.ppm", process::id()));
+    let start = Instant::now();
+    save_ppm(&image, &temp.to_string_lossy())?;
+    rows.push(("save".to_string(), start.elapsed().as_secs_f64()));
+    let _ = fs::remove_file(&temp);
+
+    let pixels = (image.width * image.height) as f64;
+    let label = rows.iter().map(|row| row.0.len()).max().unwrap_or(0).max(4);
+    println!("{}x{} ({} pixels)", image.width, image.height, pixels);
+    println!(
+        "{:<label$} {:>10} {:>12}",
+        "step",
+        "ms",
+        "ns/pixel",
+        label = label
+    );
+    for (name, seconds) in &rows {
+        println!(
+            "{:<label$} {:>10.2} {:>12.2}",
+            name,
+            seconds * 1e3,
+            seconds * 1e9 / pixels.max(1.0),
+            label = label
+        );
+    }
+    Ok(())
+}
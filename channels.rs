@@ -0,0 +1,83 @@
+use crate::{luminance, Pixel, PpmFile};
+
+fn channel_index(name: char) -> Result<usize, String> {
+    match name {
+        'r' => Ok(0),
+        'g' => Ok(1),
+        'b' => Ok(2),
+        _ => Err(format!("Unknown channel: {}", name)),
+    }
+}
+
+fn get(pixel: &Pixel, c: usize) -> f32 {
+    match c {
+        0 => pixel.r,
+        1 => pixel.g,
+        _ => pixel.b,
+    }
+}
+
+// Grayscale image of a single channel, given as r, g or b
+pub fn extract_channel(image: &mut PpmFile, name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => channel_index(c)?,
+        _ => return Err(format!("Expected a single channel but {} given!", name)),
+    };
+    for pixel in &mut image.pixels {
+        let val = get(pixel, c);
+        pixel.r = val;
+        pixel.g = val;
+        pixel.b = val;
+    }
+    Ok(())
+}
+
+// Rearranges the channels, the order names the source of every output
+// channel, e.g. "bgr" swaps red and blue and "rrr" copies red everywhere
+pub fn swap_channels(image: &mut PpmFile, order: &str) -> Result<(), String> {
+    let sources = order
+        .chars()
+        .map(channel_index)
+        .collect::<Result<Vec<usize>, String>>()?;
+    if sources.len() != 3 {
+        return Err(format!("Expected three channels but {} given!", order));
+    }
+    for pixel in &mut image.pixels {
+        let old = pixel.clone();
+        pixel.r = get(&old, sources[0]);
+        pixel.g = get(&old, sources[1]);
+        pixel.b = get(&old, sources[2]);
+    }
+    Ok(())
+}
+
+// RGB image out of the luminance of three equally sized images
+pub fn combine(red: &PpmFile, green: &PpmFile, blue: &PpmFile) -> Result<PpmFile, String> {
+    for image in &[green, blue] {
+        if image.width != red.width || image.height != red.height {
+            return Err(format!(
+                "Channel images should have the same size but {}x{} and {}x{} given!",
+                red.width, red.height, image.width, image.height
+            ));
+        }
+    }
+    Ok(PpmFile {
+        width: red.width,
+        height: red.height,
+        max_val: 255,
+        alpha: None,
+        comments: Vec::new(),
+        pixels: red
+            .pixels
+            .iter()
+            .zip(&green.pixels)
+            .zip(&blue.pixels)
+            .map(|((r, g), b)| Pixel {
+                r: luminance(r),
+                g: luminance(g),
+                b: luminance(b),
+            })
+            .collect(),
+    })
+}
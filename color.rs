@@ -0,0 +1,310 @@
+use crate::colorspace::{hsv_to_rgb, rgb_to_hsv, rgb_to_ycbcr};
+use crate::{luminance, Pixel, PpmFile};
+
+// Values this close to the maximum are considered clipped
+const CLIP_LEVEL: f32 = 254.5 / 255.0;
+
+// sRGB transfer function, the encoded 0-1 values of the files to linear light
+pub fn srgb_to_linear(val: f32) -> f32 {
+    if val <= 0.04045 {
+        val / 12.92
+    } else {
+        ((val + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(val: f32) -> f32 {
+    if val <= 0.0031308 {
+        val * 12.92
+    } else {
+        1.055 * val.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Decodes the image to linear light so blurs and blends mix actual intensities
+pub fn to_linear(image: &mut PpmFile) {
+    for pixel in &mut image.pixels {
+        pixel.r = srgb_to_linear(pixel.r);
+        pixel.g = srgb_to_linear(pixel.g);
+        pixel.b = srgb_to_linear(pixel.b);
+    }
+}
+
+pub fn to_srgb(image: &mut PpmFile) {
+    for pixel in &mut image.pixels {
+        pixel.r = linear_to_srgb(pixel.r);
+        pixel.g = linear_to_srgb(pixel.g);
+        pixel.b = linear_to_srgb(pixel.b);
+    }
+}
+
+fn channels(pixel: &Pixel) -> [f32; 3] {
+    [pixel.r, pixel.g, pixel.b]
+}
+
+// Rebuilds channels clipped at the maximum from the ratios between channels
+// of unclipped pixels within the radius. The result is desaturated towards
+// white just enough to keep the original brightness inside the 0-1 range.
+pub fn reconstruct_highlights(image: &mut PpmFile, radius: usize) {
+    let pixels = image.pixels.clone();
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let pixel = &pixels[y * image.width + x];
+            let vals = channels(pixel);
+            let clipped: Vec<bool> = vals.iter().map(|&v| v >= CLIP_LEVEL).collect();
+            let clipped_count = clipped.iter().filter(|&&c| c).count();
+            if clipped_count == 0 || clipped_count == 3 {
+                continue;
+            }
+
+            // mean of the channels that survived, both here and in the neighbours
+            let unclipped_mean = |vals: &[f32; 3]| {
+                let mut sum = 0.0;
+                for c in 0..3 {
+                    if !clipped[c] {
+                        sum += vals[c];
+                    }
+                }
+                sum / (3 - clipped_count) as f32
+            };
+            let reference = unclipped_mean(&vals);
+
+            let mut ratios = [0.0f32; 3];
+            let mut samples = 0;
+            for ny in y.saturating_sub(radius)..usize::min(y + radius + 1, image.height) {
+                for nx in x.saturating_sub(radius)..usize::min(x + radius + 1, image.width) {
+                    let neighbour = channels(&pixels[ny * image.width + nx]);
+                    if neighbour.iter().any(|&v| v >= CLIP_LEVEL) {
+                        continue;
+                    }
+                    let mean = unclipped_mean(&neighbour);
+                    if mean <= f32::EPSILON {
+                        continue;
+                    }
+                    for c in 0..3 {
+                        ratios[c] += neighbour[c] / mean;
+                    }
+                    samples += 1;
+                }
+            }
+
+            let mut rebuilt = vals;
+            if samples > 0 {
+                for c in 0..3 {
+                    if clipped[c] {
+                        rebuilt[c] = f32::max(reference * ratios[c] / samples as f32, vals[c]);
+                    }
+                }
+            } else {
+                // nothing to learn the color from, fall back to white
+                rebuilt = [1.0, 1.0, 1.0];
+            }
+
+            let max = rebuilt.iter().cloned().fold(0.0, f32::max);
+            let mut out = Pixel {
+                r: rebuilt[0] / max,
+                g: rebuilt[1] / max,
+                b: rebuilt[2] / max,
+            };
+            let target = luminance(pixel);
+            let current = luminance(&out);
+            if target > current && current < 1.0 {
+                let k = (target - current) / (1.0 - current);
+                out.r += (1.0 - out.r) * k;
+                out.g += (1.0 - out.g) * k;
+                out.b += (1.0 - out.b) * k;
+            }
+            image.pixels[y * image.width + x] = out;
+        }
+    }
+}
+
+// Classic sepia matrix blended with the original by the intensity (0-1)
+pub fn apply_sepia(image: &mut PpmFile, intensity: f32) {
+    for pixel in &mut image.pixels {
+        let r = f32::min(pixel.r * 0.393 + pixel.g * 0.769 + pixel.b * 0.189, 1.0);
+        let g = f32::min(pixel.r * 0.349 + pixel.g * 0.686 + pixel.b * 0.168, 1.0);
+        let b = f32::min(pixel.r * 0.272 + pixel.g * 0.534 + pixel.b * 0.131, 1.0);
+        pixel.r += (r - pixel.r) * intensity;
+        pixel.g += (g - pixel.g) * intensity;
+        pixel.b += (b - pixel.b) * intensity;
+    }
+}
+
+pub fn apply_invert(image: &mut PpmFile) {
+    for pixel in &mut image.pixels {
+        pixel.r = 1.0 - pixel.r;
+        pixel.g = 1.0 - pixel.g;
+        pixel.b = 1.0 - pixel.b;
+    }
+}
+
+// Inverts the channel values above the threshold, like a print exposed to
+// light while it develops
+pub fn apply_solarize(image: &mut PpmFile, threshold: f32) {
+    let solarize = |val: f32| if val > threshold { 1.0 - val } else { val };
+    for pixel in &mut image.pixels {
+        pixel.r = solarize(pixel.r);
+        pixel.g = solarize(pixel.g);
+        pixel.b = solarize(pixel.b);
+    }
+}
+
+pub struct Levels {
+    pub in_black: f32,
+    pub in_white: f32,
+    pub gamma: f32,
+    pub out_black: f32,
+    pub out_white: f32,
+}
+
+impl Levels {
+    fn map(&self, val: f32) -> f32 {
+        let range = f32::max(self.in_white - self.in_black, f32::EPSILON);
+        let val = ((val - self.in_black) / range).clamp(0.0, 1.0);
+        self.out_black + val.powf(1.0 / self.gamma) * (self.out_white - self.out_black)
+    }
+}
+
+// Channels are picked by the mask as [r, g, b]
+pub fn apply_levels(image: &mut PpmFile, levels: &Levels, channels: [bool; 3]) {
+    for pixel in &mut image.pixels {
+        if channels[0] {
+            pixel.r = levels.map(pixel.r);
+        }
+        if channels[1] {
+            pixel.g = levels.map(pixel.g);
+        }
+        if channels[2] {
+            pixel.b = levels.map(pixel.b);
+        }
+    }
+}
+
+// Rotates the hue by hue_shift degrees and scales saturation and value
+pub fn apply_hsv(image: &mut PpmFile, hue_shift: f32, saturation: f32, value: f32) {
+    for pixel in &mut image.pixels {
+        let (h, s, v) = rgb_to_hsv(pixel);
+        *pixel = hsv_to_rgb(
+            h + hue_shift,
+            (s * saturation).clamp(0.0, 1.0),
+            (v * value).clamp(0.0, 1.0),
+        );
+    }
+}
+
+pub enum WhiteBalance {
+    // the average color of the scene is assumed to be gray
+    GrayWorld,
+    // the brightest percent of the pixels is assumed to be white
+    WhitePatch,
+}
+
+pub fn apply_white_balance(image: &mut PpmFile, method: WhiteBalance) {
+    if image.pixels.is_empty() {
+        return;
+    }
+    let reference: Vec<&Pixel> = match method {
+        WhiteBalance::GrayWorld => image.pixels.iter().collect(),
+        WhiteBalance::WhitePatch => {
+            let mut sorted: Vec<&Pixel> = image.pixels.iter().collect();
+            sorted.sort_by(|a, b| luminance(b).total_cmp(&luminance(a)));
+            sorted.truncate(usize::max(sorted.len() / 100, 1));
+            sorted
+        }
+    };
+
+    let count = reference.len() as f32;
+    let means = [
+        reference.iter().map(|p| p.r).sum::<f32>() / count,
+        reference.iter().map(|p| p.g).sum::<f32>() / count,
+        reference.iter().map(|p| p.b).sum::<f32>() / count,
+    ];
+    let target = match method {
+        WhiteBalance::GrayWorld => (means[0] + means[1] + means[2]) / 3.0,
+        WhiteBalance::WhitePatch => means.iter().cloned().fold(0.0, f32::max),
+    };
+    let gains: Vec<f32> = means
+        .iter()
+        .map(|&mean| if mean > 0.0 { target / mean } else { 1.0 })
+        .collect();
+
+    for pixel in &mut image.pixels {
+        pixel.r = f32::min(pixel.r * gains[0], 1.0);
+        pixel.g = f32::min(pixel.g * gains[1], 1.0);
+        pixel.b = f32::min(pixel.b * gains[2], 1.0);
+    }
+}
+
+// Approximate color of a black body at the given temperature (Tanner Helland's fit)
+fn kelvin_to_rgb(kelvin: f32) -> [f32; 3] {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let g = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.0).powf(-0.075514846)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+    [
+        r.clamp(1.0, 255.0) / 255.0,
+        g.clamp(1.0, 255.0) / 255.0,
+        b.clamp(1.0, 255.0) / 255.0,
+    ]
+}
+
+// Corrects for light of the given color temperature (6500K leaves the image
+// as it is, lower values cool it down and higher ones warm it up) and shifts
+// it towards magenta (positive tint) or green (negative tint)
+pub fn apply_temperature(image: &mut PpmFile, kelvin: f32, tint: f32) {
+    let neutral = kelvin_to_rgb(6500.0);
+    let light = kelvin_to_rgb(kelvin);
+    let mut gains = [
+        neutral[0] / light[0] * (1.0 + tint * 0.25),
+        neutral[1] / light[1] * (1.0 - tint * 0.25),
+        neutral[2] / light[2] * (1.0 + tint * 0.25),
+    ];
+    // keep the overall brightness
+    let lum = gains[0] * 0.2126 + gains[1] * 0.7152 + gains[2] * 0.0722;
+    for gain in &mut gains {
+        *gain /= lum;
+    }
+
+    for pixel in &mut image.pixels {
+        pixel.r = (pixel.r * gains[0]).clamp(0.0, 1.0);
+        pixel.g = (pixel.g * gains[1]).clamp(0.0, 1.0);
+        pixel.b = (pixel.b * gains[2]).clamp(0.0, 1.0);
+    }
+}
+
+// Blue and red difference chroma (BT.709), independent of the brightness
+fn chroma(pixel: &Pixel) -> (f32, f32) {
+    let (_, cb, cr) = rgb_to_ycbcr(pixel);
+    (cb, cr)
+}
+
+// Replaces the pixels whose chroma lies within the tolerance of the key
+// color's with the background, so shadows on the screen are keyed out too.
+// Images with an alpha channel get those pixels cleared as well.
+pub fn apply_chroma_key(image: &mut PpmFile, key: &Pixel, tolerance: f32, background: &Pixel) {
+    let (key_cb, key_cr) = chroma(key);
+    for (i, pixel) in image.pixels.iter_mut().enumerate() {
+        let (cb, cr) = chroma(pixel);
+        if f32::hypot(cb - key_cb, cr - key_cr) <= tolerance {
+            *pixel = background.clone();
+            if let Some(alpha) = &mut image.alpha {
+                alpha[i] = 0.0;
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::fs;
+
+use crate::{luminance, parse_color, Pixel, PpmFile};
+
+// Evenly spaced stops sampled from the matplotlib colormaps
+const VIRIDIS: [&str; 9] = [
+    "#440154", "#482878", "#3e4989", "#31688e", "#26828e", "#1f9e89", "#35b779", "#6ece58",
+    "#fde725",
+];
+const MAGMA: [&str; 9] = [
+    "#000004", "#1c1044", "#4f127b", "#812581", "#b5367a", "#e55064", "#fb8761", "#fec287",
+    "#fcfdbf",
+];
+const JET: [&str; 9] = [
+    "#000080", "#0000ff", "#0080ff", "#00ffff", "#80ff80", "#ffff00", "#ff8000", "#ff0000",
+    "#800000",
+];
+const GRAY: [&str; 2] = ["#000000", "#ffffff"];
+
+pub struct Colormap {
+    stops: Vec<Pixel>,
+}
+
+impl Colormap {
+    // One of the builtin maps or a ramp file with one color per line, either as
+    // `#rrggbb` or as three 0-255 values. Lines starting with `#` followed by a
+    // space are comments.
+    pub fn load(name: &str) -> Result<Colormap, String> {
+        let builtin: Option<&[&str]> = match name {
+            "viridis" => Some(&VIRIDIS),
+            "magma" => Some(&MAGMA),
+            "jet" => Some(&JET),
+            "gray" => Some(&GRAY),
+            _ => None,
+        };
+        let stops = match builtin {
+            Some(colors) => colors
+                .iter()
+                .map(|color| parse_color(color))
+                .collect::<Result<Vec<Pixel>, String>>()?,
+            None => {
+                let ramp = fs::read_to_string(name)
+                    .map_err(|error| format!("Could not read colormap {}: {}", name, error))?;
+                let mut stops = Vec::new();
+                for line in ramp.lines().map(str::trim) {
+                    if line.is_empty() || line.starts_with("# ") {
+                        continue;
+                    }
+                    if line.starts_with('#') {
+                        stops.push(parse_color(line)?);
+                        continue;
+                    }
+                    let vals = line
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|val| !val.is_empty())
+                        .map(|val| val.parse::<u8>())
+                        .collect::<Result<Vec<u8>, _>>()
+                        .map_err(|error| format!("Bad colormap entry {}: {}", line, error))?;
+                    if vals.len() != 3 {
+                        return Err(format!("Expected three values but found: {}", line));
+                    }
+                    stops.push(Pixel {
+                        r: vals[0] as f32 / 255.0,
+                        g: vals[1] as f32 / 255.0,
+                        b: vals[2] as f32 / 255.0,
+                    });
+                }
+                stops
+            }
+        };
+        if stops.len() < 2 {
+            return Err(format!("Colormap {} needs at least two colors!", name));
+        }
+        Ok(Colormap { stops })
+    }
+
+    // Linear interpolation between the two stops surrounding t
+    pub fn sample(&self, t: f32) -> Pixel {
+        let pos = t.clamp(0.0, 1.0) * (self.stops.len() - 1) as f32;
+        let i = usize::min(pos as usize, self.stops.len() - 2);
+        let frac = pos - i as f32;
+        let a = &self.stops[i];
+        let b = &self.stops[i + 1];
+        Pixel {
+            r: a.r + (b.r - a.r) * frac,
+            g: a.g + (b.g - a.g) * frac,
+            b: a.b + (b.b - a.b) * frac,
+        }
+    }
+}
+
+pub fn apply_colormap(image: &mut PpmFile, colormap: &Colormap) {
+    for pixel in &mut image.pixels {
+        *pixel = colormap.sample(luminance(pixel));
+    }
+}
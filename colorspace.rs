@@ -0,0 +1,169 @@
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::{luminance, Pixel, PpmFile};
+
+// D65 white point in XYZ
+const WHITE: (f32, f32, f32) = (0.95047, 1.0, 1.08883);
+
+// Hue in degrees [0, 360), saturation and value in [0, 1]
+pub fn rgb_to_hsv(pixel: &Pixel) -> (f32, f32, f32) {
+    let max = f32::max(pixel.r, f32::max(pixel.g, pixel.b));
+    let min = f32::min(pixel.r, f32::min(pixel.g, pixel.b));
+    let delta = max - min;
+    let hue = if delta <= 0.0 {
+        0.0
+    } else if max == pixel.r {
+        60.0 * ((pixel.g - pixel.b) / delta).rem_euclid(6.0)
+    } else if max == pixel.g {
+        60.0 * ((pixel.b - pixel.r) / delta + 2.0)
+    } else {
+        60.0 * ((pixel.r - pixel.g) / delta + 4.0)
+    };
+    let saturation = if max > 0.0 { delta / max } else { 0.0 };
+    (hue, saturation, max)
+}
+
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> Pixel {
+    let hue = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    Pixel {
+        r: r + m,
+        g: g + m,
+        b: b + m,
+    }
+}
+
+// Rec. 709 luma in [0, 1] and chroma in [-0.5, 0.5]
+pub fn rgb_to_ycbcr(pixel: &Pixel) -> (f32, f32, f32) {
+    let y = luminance(pixel);
+    (y, (pixel.b - y) / 1.8556, (pixel.r - y) / 1.5748)
+}
+
+pub fn ycbcr_to_rgb(y: f32, cb: f32, cr: f32) -> Pixel {
+    let r = y + 1.5748 * cr;
+    let b = y + 1.8556 * cb;
+    let g = (y - 0.2126 * r - 0.0722 * b) / 0.7152;
+    Pixel {
+        r: r.clamp(0.0, 1.0),
+        g: g.clamp(0.0, 1.0),
+        b: b.clamp(0.0, 1.0),
+    }
+}
+
+// sRGB to CIELAB under the D65 white point, L in [0, 100] and a, b about
+// [-128, 127]
+pub fn rgb_to_lab(pixel: &Pixel) -> (f32, f32, f32) {
+    let r = srgb_to_linear(pixel.r);
+    let g = srgb_to_linear(pixel.g);
+    let b = srgb_to_linear(pixel.b);
+    let x = (r * 0.4124 + g * 0.3576 + b * 0.1805) / WHITE.0;
+    let y = (r * 0.2126 + g * 0.7152 + b * 0.0722) / WHITE.1;
+    let z = (r * 0.0193 + g * 0.1192 + b * 0.9505) / WHITE.2;
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+// Colors outside of the sRGB gamut are clamped
+pub fn lab_to_rgb(l: f32, a: f32, b: f32) -> Pixel {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let f_inv = |t: f32| {
+        if t > 6.0 / 29.0 {
+            t * t * t
+        } else {
+            (t - 16.0 / 116.0) / 7.787
+        }
+    };
+    let x = f_inv(fx) * WHITE.0;
+    let y = f_inv(fy) * WHITE.1;
+    let z = f_inv(fz) * WHITE.2;
+    let encode = |val: f32| linear_to_srgb(val.clamp(0.0, 1.0));
+    Pixel {
+        r: encode(x * 3.2406 - y * 1.5372 - z * 0.4986),
+        g: encode(-x * 0.9689 + y * 1.8758 + z * 0.0415),
+        b: encode(x * 0.0557 - y * 0.2040 + z * 1.0570),
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ColorSpace {
+    Hsv,
+    YCbCr,
+    Lab,
+}
+
+impl ColorSpace {
+    pub fn parse(name: &str) -> Result<ColorSpace, String> {
+        match name {
+            "hsv" => Ok(ColorSpace::Hsv),
+            "ycbcr" => Ok(ColorSpace::YCbCr),
+            "lab" => Ok(ColorSpace::Lab),
+            _ => Err(format!(
+                "Expected a color space as hsv, ycbcr or lab but {} given!",
+                name
+            )),
+        }
+    }
+}
+
+// Stores the channels of the color space in r, g and b, every one scaled to
+// [0, 1], so the following steps filter e.g. the lightness alone
+pub fn to_space(image: &mut PpmFile, space: ColorSpace) {
+    for pixel in &mut image.pixels {
+        let (c0, c1, c2) = match space {
+            ColorSpace::Hsv => {
+                let (h, s, v) = rgb_to_hsv(pixel);
+                (h / 360.0, s, v)
+            }
+            ColorSpace::YCbCr => {
+                let (y, cb, cr) = rgb_to_ycbcr(pixel);
+                (y, cb + 0.5, cr + 0.5)
+            }
+            ColorSpace::Lab => {
+                let (l, a, b) = rgb_to_lab(pixel);
+                (l / 100.0, (a + 128.0) / 255.0, (b + 128.0) / 255.0)
+            }
+        };
+        *pixel = Pixel {
+            r: c0,
+            g: c1,
+            b: c2,
+        };
+    }
+}
+
+// Back to RGB from the scaled channels of to_space
+pub fn from_space(image: &mut PpmFile, space: ColorSpace) {
+    for pixel in &mut image.pixels {
+        *pixel = match space {
+            ColorSpace::Hsv => hsv_to_rgb(
+                pixel.r * 360.0,
+                pixel.g.clamp(0.0, 1.0),
+                pixel.b.clamp(0.0, 1.0),
+            ),
+            ColorSpace::YCbCr => ycbcr_to_rgb(pixel.r, pixel.g - 0.5, pixel.b - 0.5),
+            ColorSpace::Lab => lab_to_rgb(
+                pixel.r * 100.0,
+                pixel.g * 255.0 - 128.0,
+                pixel.b * 255.0 - 128.0,
+            ),
+        };
+    }
+}
@@ -0,0 +1,91 @@
+use crate::colormap::Colormap;
+use crate::kernel::{convolve_channel, Border, Kernel};
+use crate::{GrayImage, Pixel, PpmFile};
+
+fn same_size(a: &PpmFile, b: &PpmFile) -> Result<(), String> {
+    if a.width != b.width || a.height != b.height {
+        return Err(format!(
+            "Images of the same size are needed but {}x{} and {}x{} given!",
+            a.width, a.height, b.width, b.height
+        ));
+    }
+    Ok(())
+}
+
+// Peak signal to noise ratio in dB over all channels, infinite for identical images
+pub fn psnr(a: &PpmFile, b: &PpmFile) -> Result<f64, String> {
+    same_size(a, b)?;
+    let mut sum = 0.0f64;
+    for (p, q) in a.pixels.iter().zip(&b.pixels) {
+        for d in &[p.r - q.r, p.g - q.g, p.b - q.b] {
+            sum += (*d as f64) * (*d as f64);
+        }
+    }
+    let mse = sum / (a.pixels.len() * 3).max(1) as f64;
+    Ok(10.0 * (1.0 / mse).log10())
+}
+
+// Mean structural similarity of the luminance, with the usual 11*11 gaussian
+// window of sigma 1.5. 1 for identical images.
+pub fn ssim(a: &PpmFile, b: &PpmFile) -> Result<f64, String> {
+    same_size(a, b)?;
+    let x = GrayImage::from_luminance(a).values;
+    let y = GrayImage::from_luminance(b).values;
+    let window = Kernel::gaussian(11, 1.5)?;
+    let blur =
+        |values: &[f32]| convolve_channel(values, a.width, a.height, &window, Border::Replicate);
+    let product =
+        |p: &[f32], q: &[f32]| -> Vec<f32> { p.iter().zip(q).map(|(p, q)| p * q).collect() };
+
+    let mu_x = blur(&x);
+    let mu_y = blur(&y);
+    let xx = blur(&product(&x, &x));
+    let yy = blur(&product(&y, &y));
+    let xy = blur(&product(&x, &y));
+
+    let c1 = 0.01f32 * 0.01;
+    let c2 = 0.03f32 * 0.03;
+    let mut sum = 0.0f64;
+    for i in 0..x.len() {
+        let var_x = xx[i] - mu_x[i] * mu_x[i];
+        let var_y = yy[i] - mu_y[i] * mu_y[i];
+        let cov = xy[i] - mu_x[i] * mu_y[i];
+        let ssim = ((2.0 * mu_x[i] * mu_y[i] + c1) * (2.0 * cov + c2))
+            / ((mu_x[i] * mu_x[i] + mu_y[i] * mu_y[i] + c1) * (var_x + var_y + c2));
+        sum += ssim as f64;
+    }
+    Ok(sum / x.len().max(1) as f64)
+}
+
+// |a - b| per channel amplified by the gain, or the largest channel difference
+// through the colormap when one is given
+pub fn difference(
+    a: &PpmFile,
+    b: &PpmFile,
+    gain: f32,
+    colormap: Option<&Colormap>,
+) -> Result<PpmFile, String> {
+    same_size(a, b)?;
+    let pixels = a
+        .pixels
+        .iter()
+        .zip(&b.pixels)
+        .map(|(p, q)| {
+            let r = ((p.r - q.r).abs() * gain).min(1.0);
+            let g = ((p.g - q.g).abs() * gain).min(1.0);
+            let b = ((p.b - q.b).abs() * gain).min(1.0);
+            match colormap {
+                Some(colormap) => colormap.sample(r.max(g).max(b)),
+                None => Pixel { r, g, b },
+            }
+        })
+        .collect();
+    Ok(PpmFile {
+        width: a.width,
+        height: a.height,
+        max_val: 255,
+        alpha: None,
+        comments: Vec::new(),
+        pixels,
+    })
+}
@@ -0,0 +1,150 @@
+use std::mem;
+
+use crate::{Pixel, PpmError, PpmFile};
+
+// Lays the images out row by row in a grid of the given number of columns,
+// each one centered in a cell as large as the largest image
+pub fn montage(
+    images: &[PpmFile],
+    columns: usize,
+    gap: usize,
+    background: &Pixel,
+) -> Result<PpmFile, PpmError> {
+    let columns = columns.clamp(1, images.len().max(1));
+    let rows = images.len().div_ceil(columns);
+    let cell_w = images.iter().map(|image| image.width).max().unwrap_or(0);
+    let cell_h = images.iter().map(|image| image.height).max().unwrap_or(0);
+    // cells*cell + (cells + 1)*gap, checked as large cells or gaps can overflow
+    let extent = |cells: usize, cell: usize| {
+        cells
+            .checked_mul(cell)?
+            .checked_add(cells.checked_add(1)?.checked_mul(gap)?)
+    };
+    let (width, height) = match (extent(columns, cell_w), extent(rows, cell_h)) {
+        (Some(width), Some(height)) => (width, height),
+        (width, height) => {
+            return Err(PpmError::DimensionOverflow {
+                width: width.unwrap_or(usize::MAX),
+                height: height.unwrap_or(usize::MAX),
+            })
+        }
+    };
+
+    let mut sheet = PpmFile::new(width, height)?;
+    for pixel in &mut sheet.pixels {
+        *pixel = background.clone();
+    }
+    for (i, image) in images.iter().enumerate() {
+        let left = gap + (i % columns) * (cell_w + gap) + (cell_w - image.width) / 2;
+        let top = gap + (i / columns) * (cell_h + gap) + (cell_h - image.height) / 2;
+        for y in 0..image.height {
+            let row = &image.pixels[y * image.width..(y + 1) * image.width];
+            let start = (top + y) * width + left;
+            sheet.pixels[start..start + image.width].clone_from_slice(row);
+        }
+    }
+    Ok(sheet)
+}
+
+#[derive(Clone, Copy)]
+pub enum Blend {
+    Normal,
+    Multiply,
+    Screen,
+}
+
+impl Blend {
+    pub fn parse(name: &str) -> Result<Blend, String> {
+        match name {
+            "normal" => Ok(Blend::Normal),
+            "multiply" => Ok(Blend::Multiply),
+            "screen" => Ok(Blend::Screen),
+            _ => Err(format!("Unknown blend mode: {}", name)),
+        }
+    }
+
+    fn apply(self, base: f32, top: f32) -> f32 {
+        match self {
+            Blend::Normal => top,
+            Blend::Multiply => base * top,
+            Blend::Screen => 1.0 - (1.0 - base) * (1.0 - top),
+        }
+    }
+}
+
+// Blends the overlay onto the image with its top left corner at (x, y), mixed
+// in by the opacity and the alpha of the overlay. The parts falling outside of
+// the image are dropped.
+pub fn compose(image: &mut PpmFile, overlay: &PpmFile, x: i64, y: i64, opacity: f32, blend: Blend) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    let mix = |base: f32, top: f32, opacity: f32| base + (blend.apply(base, top) - base) * opacity;
+    for oy in 0..overlay.height {
+        let iy = y + oy as i64;
+        if iy < 0 || iy >= image.height as i64 {
+            continue;
+        }
+        for ox in 0..overlay.width {
+            let ix = x + ox as i64;
+            if ix < 0 || ix >= image.width as i64 {
+                continue;
+            }
+            let top = &overlay.pixels[oy * overlay.width + ox];
+            let opacity = match &overlay.alpha {
+                Some(alpha) => opacity * alpha[oy * overlay.width + ox],
+                None => opacity,
+            };
+            let i = iy as usize * image.width + ix as usize;
+            let base = &mut image.pixels[i];
+            base.r = mix(base.r, top.r, opacity);
+            base.g = mix(base.g, top.g, opacity);
+            base.b = mix(base.b, top.b, opacity);
+            // the overlay covers a transparent image the way it would be painted over it
+            if let Some(alpha) = &mut image.alpha {
+                alpha[i] += (1.0 - alpha[i]) * opacity;
+            }
+        }
+    }
+}
+
+// Centers the image on a width*height canvas filled with the color
+pub fn pad(image: &mut PpmFile, width: usize, height: usize, fill: &Pixel) -> Result<(), PpmError> {
+    let mut canvas = PpmFile::new(width, height)?;
+    for pixel in &mut canvas.pixels {
+        *pixel = fill.clone();
+    }
+    compose(
+        &mut canvas,
+        image,
+        (width as i64 - image.width as i64) / 2,
+        (height as i64 - image.height as i64) / 2,
+        1.0,
+        Blend::Normal,
+    );
+    canvas.max_val = image.max_val;
+    canvas.comments = mem::take(&mut image.comments);
+    *image = canvas;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn montage_size_overflow_is_an_error() {
+        let images = [PpmFile::new(2, 1).unwrap(), PpmFile::new(1, 2).unwrap()];
+        let black = Pixel {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+        };
+        let sheet = montage(&images, 2, 1, &black).unwrap();
+        assert_eq!((sheet.width, sheet.height), (7, 4));
+        for gap in [usize::MAX, usize::MAX / 3] {
+            assert!(matches!(
+                montage(&images, 2, gap, &black),
+                Err(PpmError::DimensionOverflow { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::draw::draw_line;
+use crate::{luminance, Pixel, PpmFile};
+
+// Where the level crosses the edge between two samples
+fn crossing(a: (f32, f32, f32), b: (f32, f32, f32), level: f32) -> (f32, f32) {
+    let t = (level - a.2) / (b.2 - a.2);
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+// Marching squares over the luminance, one iso line every `interval`
+pub fn draw_contours(image: &mut PpmFile, interval: f32, color: &Pixel) -> Result<(), String> {
+    if interval <= 0.0 || interval >= 1.0 {
+        return Err(format!(
+            "The contour interval should be in (0, 1) but {} given!",
+            interval
+        ));
+    }
+    let width = image.width;
+    let lums: Vec<f32> = image.pixels.iter().map(luminance).collect();
+    let mut segments: Vec<((f32, f32), (f32, f32))> = Vec::new();
+
+    let mut level = interval;
+    while level < 1.0 {
+        for y in 0..image.height.saturating_sub(1) {
+            for x in 0..width.saturating_sub(1) {
+                // corners clockwise from the top left one
+                let corners = [
+                    (x as f32, y as f32, lums[y * width + x]),
+                    (x as f32 + 1.0, y as f32, lums[y * width + x + 1]),
+                    (
+                        x as f32 + 1.0,
+                        y as f32 + 1.0,
+                        lums[(y + 1) * width + x + 1],
+                    ),
+                    (x as f32, y as f32 + 1.0, lums[(y + 1) * width + x]),
+                ];
+                let above: Vec<bool> = corners.iter().map(|c| c.2 >= level).collect();
+
+                // top, right, bottom and left edge crossings
+                let mut points = [None; 4];
+                for edge in 0..4 {
+                    let next = (edge + 1) % 4;
+                    if above[edge] != above[next] {
+                        points[edge] = Some(crossing(corners[edge], corners[next], level));
+                    }
+                }
+
+                match points {
+                    [Some(top), Some(right), Some(bottom), Some(left)] => {
+                        // saddle, let the center decide which corners are connected
+                        let center = corners.iter().map(|c| c.2).sum::<f32>() / 4.0;
+                        if (center >= level) == above[0] {
+                            segments.push((top, right));
+                            segments.push((bottom, left));
+                        } else {
+                            segments.push((top, left));
+                            segments.push((right, bottom));
+                        }
+                    }
+                    _ => {
+                        let found: Vec<(f32, f32)> = points.iter().flatten().cloned().collect();
+                        if found.len() == 2 {
+                            segments.push((found[0], found[1]));
+                        }
+                    }
+                }
+            }
+        }
+        level += interval;
+    }
+
+    for (from, to) in segments {
+        draw_line(
+            image,
+            (from.0.round() as i64, from.1.round() as i64),
+            (to.0.round() as i64, to.1.round() as i64),
+            color,
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::parallel::for_each_row;
+use crate::{Pixel, PpmFile};
+
+// Non-local means: every pixel becomes the average of the pixels within the
+// search radius, weighted by how similar the patches around them are. The
+// strength h controls how quickly the weight falls off with patch distance.
+pub fn apply_nl_means(image: &mut PpmFile, h: f32, patch: usize, search: usize) {
+    let width = image.width as isize;
+    let height = image.height as isize;
+    let pixels = image.pixels.clone();
+    let patch = patch as isize;
+    let search = search as isize;
+    let h2 = f32::max(h * h, f32::EPSILON);
+    // patch samples are clamped to the image
+    let at = |x: isize, y: isize| -> &Pixel {
+        let x = x.clamp(0, width - 1);
+        let y = y.clamp(0, height - 1);
+        &pixels[(y * width + x) as usize]
+    };
+    let patch_len = ((2 * patch + 1) * (2 * patch + 1) * 3) as f32;
+
+    for_each_row(&mut image.pixels, width as usize, |y, row| {
+        let y = y as isize;
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let x = x as isize;
+            let mut sum = Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            };
+            let mut total = 0.0;
+            for sy in (y - search).max(0)..(y + search + 1).min(height) {
+                for sx in (x - search).max(0)..(x + search + 1).min(width) {
+                    let mut distance = 0.0;
+                    for py in -patch..=patch {
+                        for px in -patch..=patch {
+                            let a = at(x + px, y + py);
+                            let b = at(sx + px, sy + py);
+                            distance += (a.r - b.r) * (a.r - b.r)
+                                + (a.g - b.g) * (a.g - b.g)
+                                + (a.b - b.b) * (a.b - b.b);
+                        }
+                    }
+                    let weight = f32::exp(-distance / patch_len / h2);
+                    let candidate = at(sx, sy);
+                    sum.r += candidate.r * weight;
+                    sum.g += candidate.g * weight;
+                    sum.b += candidate.b * weight;
+                    total += weight;
+                }
+            }
+            *pixel = Pixel {
+                r: sum.r / total,
+                g: sum.g / total,
+                b: sum.b / total,
+            };
+        }
+    });
+}
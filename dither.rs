@@ -0,0 +1,130 @@
+use crate::{luminance, Pixel, PpmFile};
+
+// size*size Bayer index matrix, size being a power of two
+fn bayer_matrix(size: usize) -> Vec<usize> {
+    let mut matrix = vec![0];
+    let mut n = 1;
+    while n < size {
+        let mut next = vec![0; 4 * n * n];
+        for y in 0..n {
+            for x in 0..n {
+                let val = 4 * matrix[y * n + x];
+                next[y * 2 * n + x] = val;
+                next[y * 2 * n + x + n] = val + 2;
+                next[(y + n) * 2 * n + x] = val + 3;
+                next[(y + n) * 2 * n + x + n] = val + 1;
+            }
+        }
+        matrix = next;
+        n *= 2;
+    }
+    matrix
+}
+
+// Ordered dithering of every channel down to the given number of levels
+pub fn apply_bayer(image: &mut PpmFile, size: usize, levels: usize) -> Result<(), String> {
+    if ![2, 4, 8].contains(&size) {
+        return Err(format!(
+            "Bayer matrices are 2, 4 or 8 wide but {} given!",
+            size
+        ));
+    }
+    if levels < 2 {
+        return Err(format!("At least two levels needed but {} given!", levels));
+    }
+    let matrix = bayer_matrix(size);
+    let steps = (levels - 1) as f32;
+    let quantize = |val: f32, threshold: f32| {
+        (f32::floor(val.clamp(0.0, 1.0) * steps + threshold) / steps).min(1.0)
+    };
+    for (i, pixel) in image.pixels.iter_mut().enumerate() {
+        let x = i % image.width;
+        let y = i / image.width;
+        let threshold = (matrix[(y % size) * size + x % size] as f32 + 0.5) / (size * size) as f32;
+        pixel.r = quantize(pixel.r, threshold);
+        pixel.g = quantize(pixel.g, threshold);
+        pixel.b = quantize(pixel.b, threshold);
+    }
+    Ok(())
+}
+
+// Black dots on white whose size follows the darkness of the luminance, on a
+// grid of cell pixels turned by the angle in degrees like a printing screen
+pub fn apply_halftone(image: &mut PpmFile, cell: f32, angle: f32) -> Result<(), String> {
+    if cell.is_nan() || cell < 2.0 {
+        return Err(format!(
+            "Halftone cells are at least 2 pixels wide but {} given!",
+            cell
+        ));
+    }
+    let (sin, cos) = angle.to_radians().sin_cos();
+    // cell of a position in the turned grid
+    let grid = |x: f32, y: f32| {
+        (
+            ((x * cos + y * sin) / cell).floor() as i64,
+            ((y * cos - x * sin) / cell).floor() as i64,
+        )
+    };
+    let corners = [
+        (0.0, 0.0),
+        (image.width as f32, 0.0),
+        (0.0, image.height as f32),
+        (image.width as f32, image.height as f32),
+    ];
+    let cells: Vec<(i64, i64)> = corners.iter().map(|&(x, y)| grid(x, y)).collect();
+    let min_i = cells.iter().map(|c| c.0).min().unwrap_or(0) - 1;
+    let min_j = cells.iter().map(|c| c.1).min().unwrap_or(0) - 1;
+    let columns = (cells.iter().map(|c| c.0).max().unwrap_or(0) - min_i + 2) as usize;
+    let rows = (cells.iter().map(|c| c.1).max().unwrap_or(0) - min_j + 2) as usize;
+
+    // mean darkness of the pixels falling in every cell
+    let mut sums = vec![(0.0f32, 0u32); columns * rows];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (i, j) = grid(x as f32 + 0.5, y as f32 + 0.5);
+            let sum = &mut sums[(j - min_j) as usize * columns + (i - min_i) as usize];
+            sum.0 += 1.0 - luminance(&image.pixels[y * image.width + x]).clamp(0.0, 1.0);
+            sum.1 += 1;
+        }
+    }
+    // radius of a dot covering that share of its cell, dots of dark cells
+    // grow into their neighbours
+    let radii: Vec<f32> = sums
+        .iter()
+        .map(|&(sum, count)| {
+            let darkness = if count > 0 { sum / count as f32 } else { 0.0 };
+            cell * (darkness / std::f32::consts::PI).sqrt()
+        })
+        .collect();
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let u = (px * cos + py * sin) / cell;
+            let v = (py * cos - px * sin) / cell;
+            let (i, j) = (u.floor() as i64, v.floor() as i64);
+            let mut inside = false;
+            for nj in j - 1..=j + 1 {
+                for ni in i - 1..=i + 1 {
+                    let (ci, cj) = (ni - min_i, nj - min_j);
+                    if ci < 0 || cj < 0 || ci as usize >= columns || cj as usize >= rows {
+                        continue;
+                    }
+                    let du = (u - (ni as f32 + 0.5)) * cell;
+                    let dv = (v - (nj as f32 + 0.5)) * cell;
+                    let radius = radii[cj as usize * columns + ci as usize];
+                    if du * du + dv * dv < radius * radius {
+                        inside = true;
+                    }
+                }
+            }
+            let val = if inside { 0.0 } else { 1.0 };
+            image.pixels[y * image.width + x] = Pixel {
+                r: val,
+                g: val,
+                b: val,
+            };
+        }
+    }
+    Ok(())
+}
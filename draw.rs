@@ -0,0 +1,59 @@
+use crate::font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::{Pixel, PpmFile};
+
+// Bresenham line, the parts outside of the image are skipped
+pub fn draw_line(image: &mut PpmFile, from: (i64, i64), to: (i64, i64), color: &Pixel) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    loop {
+        if x >= 0 && y >= 0 && (x as usize) < image.width && (y as usize) < image.height {
+            image.pixels[y as usize * image.width + x as usize] = color.clone();
+        }
+        if x == to.0 && y == to.1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+// Text in the built in 5*7 font with its top left corner at (x, y), every font
+// pixel drawn as a scale*scale square. Lines are separated by newlines.
+pub fn draw_text(image: &mut PpmFile, x: i64, y: i64, scale: usize, color: &Pixel, text: &str) {
+    let scale = scale.max(1) as i64;
+    for (row, line) in text.lines().enumerate() {
+        let top = y + row as i64 * (GLYPH_HEIGHT as i64 + 1) * scale;
+        for (column, c) in line.chars().enumerate() {
+            let left = x + column as i64 * (GLYPH_WIDTH as i64 + 1) * scale;
+            for (gx, bits) in glyph(c).iter().enumerate() {
+                for gy in 0..GLYPH_HEIGHT {
+                    if bits & (1 << gy) == 0 {
+                        continue;
+                    }
+                    // the square clipped to the image
+                    let (width, height) = (image.width as i64, image.height as i64);
+                    let px0 = (left + gx as i64 * scale).clamp(0, width);
+                    let px1 = (left + (gx as i64 + 1) * scale).clamp(0, width);
+                    let py0 = (top + gy as i64 * scale).clamp(0, height);
+                    let py1 = (top + (gy as i64 + 1) * scale).clamp(0, height);
+                    for py in py0..py1 {
+                        for px in px0..px1 {
+                            image.pixels[(py * width + px) as usize] = color.clone();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
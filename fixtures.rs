@@ -0,0 +1,181 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::packed::PackedImage;
+use crate::rng::Rng;
+use crate::{apply_step, load_image, save_packed, Loaded, Pixel, PpmFile};
+
+// Fixtures are small so the golden files stay small
+const SIZE: usize = 64;
+
+// Steps run on a fixture by the golden comparison
+const CASES: &[(&str, &str)] = &[
+    ("gradient", "gray"),
+    ("gradient", "gauss"),
+    ("gradient", "levels=0.1,0.9,1.2"),
+    ("gradient", "hue-shift=90"),
+    ("gradient", "scale=40,0"),
+    ("checkerboard", "sobel"),
+    ("checkerboard", "canny"),
+    ("checkerboard", "erode=disk,2"),
+    ("checkerboard", "motion=9,30"),
+    ("checkerboard", "rotate=90"),
+    ("noise", "threshold"),
+    ("noise", "kuwahara"),
+    ("noise", "nlmeans=0.1,1,3"),
+    ("noise", "quantize=8,1"),
+];
+
+// Red grows to the right, green downwards and blue along the diagonal
+pub fn gradient(width: usize, height: usize) -> PpmFile {
+    let span = |len: usize| (len.max(2) - 1) as f32;
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            pixels.push(Pixel {
+                r: x as f32 / span(width),
+                g: y as f32 / span(height),
+                b: (x + y) as f32 / (span(width) + span(height)),
+            });
+        }
+    }
+    PpmFile {
+        width,
+        height,
+        max_val: 255,
+        alpha: None,
+        comments: Vec::new(),
+        pixels,
+    }
+}
+
+// Black and white squares of cell pixels
+pub fn checkerboard(width: usize, height: usize, cell: usize) -> PpmFile {
+    let cell = cell.max(1);
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let val = ((x / cell + y / cell) % 2) as f32;
+            pixels.push(Pixel {
+                r: val,
+                g: val,
+                b: val,
+            });
+        }
+    }
+    PpmFile {
+        width,
+        height,
+        max_val: 255,
+        alpha: None,
+        comments: Vec::new(),
+        pixels,
+    }
+}
+
+// Uniform noise on every channel, the same for the same seed
+pub fn noise(width: usize, height: usize, seed: u64) -> PpmFile {
+    let mut rng = Rng::new(seed);
+    let pixels = (0..width * height)
+        .map(|_| Pixel {
+            r: rng.next_f32(),
+            g: rng.next_f32(),
+            b: rng.next_f32(),
+        })
+        .collect();
+    PpmFile {
+        width,
+        height,
+        max_val: 255,
+        alpha: None,
+        comments: Vec::new(),
+        pixels,
+    }
+}
+
+fn fixture(name: &str) -> PpmFile {
+    match name {
+        "gradient" => gradient(SIZE, SIZE),
+        "checkerboard" => checkerboard(SIZE, SIZE, 8),
+        _ => noise(SIZE, SIZE, 0),
+    }
+}
+
+// Writes every fixture to the directory as <name>.ppm
+pub fn generate(dir: &str) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    for name in &["gradient", "checkerboard", "noise"] {
+        let path = Path::new(dir).join(format!("{}.ppm", name));
+        save_packed(&PackedImage::pack(&fixture(name)), &path.to_string_lossy())?;
+    }
+    Ok(())
+}
+
+// Runs every case and compares the result with <dir>/<fixture>-<step>.ppm,
+// allowing each byte to be off by the tolerance. With update the golden
+// files are rewritten instead.
+pub fn check(dir: &str, update: bool, tolerance: u8) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let none: Vec<String> = Vec::new();
+    let mut failed = 0;
+    for (name, step) in CASES {
+        let mut image = fixture(name);
+        apply_step(&mut image, step, &mut none.iter())?;
+        let result = PackedImage::pack(&image);
+
+        let file: String = step
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = Path::new(dir).join(format!("{}-{}.ppm", name, file));
+        let path = path.to_string_lossy();
+        if update {
+            save_packed(&result, &path)?;
+            println!("{:<14} {:<20} updated", name, step);
+            continue;
+        }
+
+        let golden = match load_image(&path)? {
+            Loaded::Packed(golden) => golden,
+            Loaded::Float(_) => return Err(format!("{} is not an 8 bit image!", path).into()),
+        };
+        let difference = if golden.width != result.width || golden.height != result.height {
+            None
+        } else {
+            golden
+                .pixels
+                .as_flattened()
+                .iter()
+                .zip(result.pixels.as_flattened())
+                .map(|(a, b)| a.abs_diff(*b))
+                .max()
+        };
+        match difference {
+            Some(difference) if difference <= tolerance => {
+                println!("{:<14} {:<20} ok ({})", name, step, difference)
+            }
+            Some(difference) => {
+                println!("{:<14} {:<20} FAILED ({})", name, step, difference);
+                failed += 1;
+            }
+            None => {
+                println!(
+                    "{:<14} {:<20} FAILED ({}x{} instead of {}x{})",
+                    name, step, result.width, result.height, golden.width, golden.height
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!(
+            "{} of {} results differ from their golden image!",
+            failed,
+            CASES.len()
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    PbmAscii,
+    PgmAscii,
+    PpmAscii,
+    PbmBinary,
+    PgmBinary,
+    PpmBinary,
+    Pam,
+    PfmColor,
+    PfmGray,
+    Qoi,
+    Bmp,
+    Farbfeld,
+    Png,
+    Jpeg,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Format::PbmAscii => "PBM (P1, ASCII)",
+            Format::PgmAscii => "PGM (P2, ASCII)",
+            Format::PpmAscii => "PPM (P3, ASCII)",
+            Format::PbmBinary => "PBM (P4, binary)",
+            Format::PgmBinary => "PGM (P5, binary)",
+            Format::PpmBinary => "PPM (P6, binary)",
+            Format::Pam => "PAM (P7)",
+            Format::PfmColor => "PFM (PF, color)",
+            Format::PfmGray => "PFM (Pf, grayscale)",
+            Format::Qoi => "QOI",
+            Format::Bmp => "BMP",
+            Format::Farbfeld => "farbfeld",
+            Format::Png => "PNG",
+            Format::Jpeg => "JPEG",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Recognizes the format from the magic bytes at the start of the file,
+// regardless of its extension
+pub fn detect_format(bytes: &[u8]) -> Option<Format> {
+    // NetPBM magic numbers are followed by whitespace or a comment
+    let netpbm = |magic: &[u8]| {
+        bytes.starts_with(magic)
+            && bytes
+                .get(magic.len())
+                .is_some_and(|&b| crate::is_space(b) || b == b'#')
+    };
+
+    if netpbm(b"P1") {
+        Some(Format::PbmAscii)
+    } else if netpbm(b"P2") {
+        Some(Format::PgmAscii)
+    } else if netpbm(b"P3") {
+        Some(Format::PpmAscii)
+    } else if netpbm(b"P4") {
+        Some(Format::PbmBinary)
+    } else if netpbm(b"P5") {
+        Some(Format::PgmBinary)
+    } else if netpbm(b"P6") {
+        Some(Format::PpmBinary)
+    } else if netpbm(b"P7") {
+        Some(Format::Pam)
+    } else if netpbm(b"PF") {
+        Some(Format::PfmColor)
+    } else if netpbm(b"Pf") {
+        Some(Format::PfmGray)
+    } else if bytes.starts_with(b"qoif") {
+        Some(Format::Qoi)
+    } else if bytes.starts_with(b"BM") && bytes.len() >= 14 {
+        Some(Format::Bmp)
+    } else if bytes.starts_with(b"farbfeld") {
+        Some(Format::Farbfeld)
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(Format::Png)
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(Format::Jpeg)
+    } else {
+        None
+    }
+}
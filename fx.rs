@@ -0,0 +1,482 @@
+use crate::parallel::for_each_row;
+use crate::{Pixel, PpmFile};
+
+#[derive(Clone, Copy)]
+enum Channel {
+    Red,
+    Green,
+    Blue,
+    // the channel the expression is evaluated for
+    Current,
+}
+
+#[derive(Clone, Copy)]
+enum Var {
+    Channel(Channel),
+    X,
+    Y,
+    Width,
+    Height,
+}
+
+#[derive(Clone, Copy)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+enum Expr {
+    Num(f32),
+    Var(Var),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    // condition ? then : otherwise
+    Select(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    // p[dx,dy] relative to the pixel or p{x,y} absolute, clamped to the image
+    Lookup {
+        relative: bool,
+        x: Box<Expr>,
+        y: Box<Expr>,
+        channel: Channel,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Num(f32),
+    Ident(String),
+    Op(&'static str),
+}
+
+// two character operators first so they win over their prefixes
+const OPS: [&str; 25] = [
+    "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "^", "<", ">", "!", "?", ":", "(",
+    ")", ",", "[", "]", "{", "}", ".",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let val = text
+                .parse::<f32>()
+                .map_err(|_| format!("Bad number in fx expression: {}", text))?;
+            tokens.push(Token::Num(val));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..usize::min(i + 2, chars.len())].iter().collect();
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| format!("Unexpected {} in fx expression!", c))?;
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive descent over the tokens, from the loosest binding operator down
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(next)) if *next == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Expected {} in fx expression but {} found!",
+                op,
+                self.describe()
+            ))
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.peek() {
+            Some(Token::Num(val)) => val.to_string(),
+            Some(Token::Ident(name)) => name.clone(),
+            Some(Token::Op(op)) => op.to_string(),
+            None => "the end".to_string(),
+        }
+    }
+
+    fn select(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.select()?;
+        self.expect(":")?;
+        let otherwise = self.select()?;
+        Ok(Expr::Select(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    // precedence climbing, ^ binds tightest and to the right
+    fn binary(&mut self, min: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let (op, precedence) = match self.peek() {
+                Some(Token::Op("||")) => (BinOp::Or, 1),
+                Some(Token::Op("&&")) => (BinOp::And, 2),
+                Some(Token::Op("==")) => (BinOp::Equal, 3),
+                Some(Token::Op("!=")) => (BinOp::NotEqual, 3),
+                Some(Token::Op("<")) => (BinOp::Less, 4),
+                Some(Token::Op("<=")) => (BinOp::LessEq, 4),
+                Some(Token::Op(">")) => (BinOp::Greater, 4),
+                Some(Token::Op(">=")) => (BinOp::GreaterEq, 4),
+                Some(Token::Op("+")) => (BinOp::Add, 5),
+                Some(Token::Op("-")) => (BinOp::Sub, 5),
+                Some(Token::Op("*")) => (BinOp::Mul, 6),
+                Some(Token::Op("/")) => (BinOp::Div, 6),
+                Some(Token::Op("%")) => (BinOp::Rem, 6),
+                Some(Token::Op("^")) => (BinOp::Pow, 8),
+                _ => return Ok(left),
+            };
+            if precedence < min {
+                return Ok(left);
+            }
+            self.pos += 1;
+            let next = if let BinOp::Pow = op {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let right = self.binary(next)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    // signs bind looser than ^, so -2^2 is -4
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.binary(8)?)));
+        }
+        if self.eat("+") {
+            return self.binary(8);
+        }
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.binary(8)?)));
+        }
+        self.primary()
+    }
+
+    fn channel(&mut self) -> Result<Channel, String> {
+        if !self.eat(".") {
+            return Ok(Channel::Current);
+        }
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let channel = match name.as_str() {
+                    "r" => Channel::Red,
+                    "g" => Channel::Green,
+                    "b" => Channel::Blue,
+                    "u" => Channel::Current,
+                    _ => return Err(format!("Unknown channel .{} in fx expression!", name)),
+                };
+                self.pos += 1;
+                Ok(channel)
+            }
+            _ => Err("Expected a channel after . in fx expression!".to_string()),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.peek().cloned();
+        match token {
+            Some(Token::Num(val)) => {
+                self.pos += 1;
+                Ok(Expr::Num(val))
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let expr = self.select()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                if name == "p" && (self.eat("[") || self.eat("{")) {
+                    let relative = self.tokens[self.pos - 1] == Token::Op("[");
+                    let x = self.select()?;
+                    self.expect(",")?;
+                    let y = self.select()?;
+                    self.expect(if relative { "]" } else { "}" })?;
+                    let channel = self.channel()?;
+                    return Ok(Expr::Lookup {
+                        relative,
+                        x: Box::new(x),
+                        y: Box::new(y),
+                        channel,
+                    });
+                }
+                if self.eat("(") {
+                    let mut args = Vec::new();
+                    if !self.eat(")") {
+                        loop {
+                            args.push(self.select()?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    let arity = match name.as_str() {
+                        "sin" | "cos" | "tan" | "abs" | "sqrt" | "exp" | "log" | "floor"
+                        | "ceil" | "round" => 1,
+                        "pow" | "min" | "max" => 2,
+                        "clamp" | "if" => 3,
+                        _ => return Err(format!("Unknown fx function: {}", name)),
+                    };
+                    if args.len() != arity {
+                        return Err(format!(
+                            "{} takes {} arguments but {} given!",
+                            name,
+                            arity,
+                            args.len()
+                        ));
+                    }
+                    return Ok(Expr::Call(name, args));
+                }
+                Ok(match name.as_str() {
+                    "r" => Expr::Var(Var::Channel(Channel::Red)),
+                    "g" => Expr::Var(Var::Channel(Channel::Green)),
+                    "b" => Expr::Var(Var::Channel(Channel::Blue)),
+                    "u" => Expr::Var(Var::Channel(Channel::Current)),
+                    "x" => Expr::Var(Var::X),
+                    "y" => Expr::Var(Var::Y),
+                    "w" | "width" => Expr::Var(Var::Width),
+                    "h" | "height" => Expr::Var(Var::Height),
+                    "pi" => Expr::Num(std::f32::consts::PI),
+                    "e" => Expr::Num(std::f32::consts::E),
+                    _ => return Err(format!("Unknown fx variable: {}", name)),
+                })
+            }
+            _ => Err(format!("Unexpected {} in fx expression!", self.describe())),
+        }
+    }
+}
+
+fn truth(val: bool) -> f32 {
+    if val {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// What an expression sees while it is evaluated
+struct Context<'a> {
+    image: &'a PpmFile,
+    x: usize,
+    y: usize,
+    // 0, 1 or 2 for red, green or blue
+    channel: usize,
+}
+
+impl Context<'_> {
+    fn sample(&self, x: f32, y: f32, channel: Channel) -> f32 {
+        let x = (x.round().max(0.0) as usize).min(self.image.width - 1);
+        let y = (y.round().max(0.0) as usize).min(self.image.height - 1);
+        let pixel = &self.image.pixels[y * self.image.width + x];
+        let index = match channel {
+            Channel::Red => 0,
+            Channel::Green => 1,
+            Channel::Blue => 2,
+            Channel::Current => self.channel,
+        };
+        [pixel.r, pixel.g, pixel.b][index]
+    }
+}
+
+impl Expr {
+    fn eval(&self, context: &Context) -> f32 {
+        match self {
+            Expr::Num(val) => *val,
+            Expr::Var(Var::Channel(channel)) => {
+                context.sample(context.x as f32, context.y as f32, *channel)
+            }
+            Expr::Var(Var::X) => context.x as f32,
+            Expr::Var(Var::Y) => context.y as f32,
+            Expr::Var(Var::Width) => context.image.width as f32,
+            Expr::Var(Var::Height) => context.image.height as f32,
+            Expr::Neg(expr) => -expr.eval(context),
+            Expr::Not(expr) => truth(expr.eval(context) == 0.0),
+            Expr::Binary(op, left, right) => {
+                let a = left.eval(context);
+                let b = right.eval(context);
+                match op {
+                    BinOp::Add => a + b,
+                    BinOp::Sub => a - b,
+                    BinOp::Mul => a * b,
+                    BinOp::Div => a / b,
+                    BinOp::Rem => a % b,
+                    BinOp::Pow => a.powf(b),
+                    BinOp::Less => truth(a < b),
+                    BinOp::LessEq => truth(a <= b),
+                    BinOp::Greater => truth(a > b),
+                    BinOp::GreaterEq => truth(a >= b),
+                    BinOp::Equal => truth(a == b),
+                    BinOp::NotEqual => truth(a != b),
+                    BinOp::And => truth(a != 0.0 && b != 0.0),
+                    BinOp::Or => truth(a != 0.0 || b != 0.0),
+                }
+            }
+            Expr::Select(condition, then, otherwise) => {
+                if condition.eval(context) != 0.0 {
+                    then.eval(context)
+                } else {
+                    otherwise.eval(context)
+                }
+            }
+            Expr::Call(name, args) => {
+                let arg = |i: usize| args[i].eval(context);
+                match name.as_str() {
+                    "sin" => arg(0).sin(),
+                    "cos" => arg(0).cos(),
+                    "tan" => arg(0).tan(),
+                    "abs" => arg(0).abs(),
+                    "sqrt" => arg(0).sqrt(),
+                    "exp" => arg(0).exp(),
+                    "log" => arg(0).ln(),
+                    "floor" => arg(0).floor(),
+                    "ceil" => arg(0).ceil(),
+                    "round" => arg(0).round(),
+                    "pow" => arg(0).powf(arg(1)),
+                    "min" => arg(0).min(arg(1)),
+                    "max" => arg(0).max(arg(1)),
+                    "clamp" => arg(0).max(arg(1)).min(arg(2)),
+                    // only the branch taken is evaluated
+                    _ => {
+                        if arg(0) != 0.0 {
+                            arg(1)
+                        } else {
+                            arg(2)
+                        }
+                    }
+                }
+            }
+            Expr::Lookup {
+                relative,
+                x,
+                y,
+                channel,
+            } => {
+                let (mut px, mut py) = (x.eval(context), y.eval(context));
+                if *relative {
+                    px += context.x as f32;
+                    py += context.y as f32;
+                }
+                context.sample(px, py, *channel)
+            }
+        }
+    }
+}
+
+fn compile(source: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+    };
+    let expr = parser.select()?;
+    if parser.pos != parser.tokens.len() {
+        return Err(format!(
+            "Unexpected {} in fx expression!",
+            parser.describe()
+        ));
+    }
+    Ok(expr)
+}
+
+// Evaluates the expression for every channel of every pixel, or one expression
+// per channel when three are given separated by `;`. The expressions are
+// parsed once before the image is touched.
+pub fn apply_fx(image: &mut PpmFile, source: &str) -> Result<(), String> {
+    let parts: Vec<&str> = source.split(';').collect();
+    let exprs = match parts.len() {
+        1 => vec![compile(parts[0])?],
+        3 => parts
+            .iter()
+            .map(|part| compile(part))
+            .collect::<Result<Vec<Expr>, String>>()?,
+        count => {
+            return Err(format!(
+                "fx expects one expression or three separated by ; but {} given!",
+                count
+            ))
+        }
+    };
+
+    let source_image = image.clone();
+    for_each_row(&mut image.pixels, image.width, |y, row| {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let mut out = [0.0f32; 3];
+            for (channel, val) in out.iter_mut().enumerate() {
+                let context = Context {
+                    image: &source_image,
+                    x,
+                    y,
+                    channel,
+                };
+                *val = exprs[channel.min(exprs.len() - 1)].eval(&context);
+            }
+            *pixel = Pixel {
+                r: out[0],
+                g: out[1],
+                b: out[2],
+            };
+        }
+    });
+    Ok(())
+}
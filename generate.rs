@@ -0,0 +1,90 @@
+use crate::fixtures::{checkerboard, gradient};
+use crate::texture::{self, Noise, NoiseParams};
+use crate::{allocated_pixel_count, Pixel, PpmFile};
+
+// The seven 75% bars of the SMPTE test pattern, from white to blue
+pub fn bars(width: usize, height: usize) -> PpmFile {
+    const COLORS: [(f32, f32, f32); 7] = [
+        (0.75, 0.75, 0.75),
+        (0.75, 0.75, 0.0),
+        (0.0, 0.75, 0.75),
+        (0.0, 0.75, 0.0),
+        (0.75, 0.0, 0.75),
+        (0.75, 0.0, 0.0),
+        (0.0, 0.0, 0.75),
+    ];
+    let mut pixels = Vec::with_capacity(width * height);
+    for _ in 0..height {
+        for x in 0..width {
+            let (r, g, b) = COLORS[x * COLORS.len() / width];
+            pixels.push(Pixel { r, g, b });
+        }
+    }
+    PpmFile {
+        width,
+        height,
+        max_val: 255,
+        comments: Vec::new(),
+        alpha: None,
+        pixels,
+    }
+}
+
+// Size given as <width>x<height>, small enough for the pixels to be allocated
+pub fn parse_size(size: &str) -> Result<(usize, usize), String> {
+    let parsed = size
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse::<usize>().ok()?, h.parse::<usize>().ok()?)));
+    match parsed {
+        Some((width, height)) if width > 0 && height > 0 => {
+            allocated_pixel_count(width, height).map_err(|error| error.to_string())?;
+            Ok((width, height))
+        }
+        _ => Err(format!(
+            "Expected a size as <width>x<height> but {} given!",
+            size
+        )),
+    }
+}
+
+// Test image of the pattern, checker squares are an eighth of the shorter side.
+// The noise parameters are only used by the noise textures.
+pub fn pattern(
+    name: &str,
+    width: usize,
+    height: usize,
+    noise: &NoiseParams,
+) -> Result<PpmFile, String> {
+    match name {
+        "gradient" => Ok(gradient(width, height)),
+        "checker" => Ok(checkerboard(width, height, usize::min(width, height) / 8)),
+        "bars" => Ok(bars(width, height)),
+        _ => match Noise::parse(name) {
+            Some(kind) => Ok(texture::generate(kind, width, height, noise)),
+            None => Err(format!(
+                "Expected a pattern as gradient, checker, bars, value, perlin or simplex but {} given!",
+                name
+            )),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_checks_the_image_fits() {
+        assert_eq!(parse_size("640x480"), Ok((640, 480)));
+        for size in [
+            "640",
+            "0x480",
+            "640x",
+            "axb",
+            "99999999999x99999999999",
+            "300000x300000",
+        ] {
+            assert!(parse_size(size).is_err(), "{} accepted", size);
+        }
+    }
+}
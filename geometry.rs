@@ -0,0 +1,188 @@
+use crate::{Pixel, PpmFile};
+
+// Lossless clockwise rotation by a multiple of 90 degrees of any pixel
+// representation, returns the pixels with the new width and height
+pub fn rotate_pixels<T: Clone>(
+    pixels: &[T],
+    width: usize,
+    height: usize,
+    degrees: i32,
+) -> Result<(Vec<T>, usize, usize), String> {
+    let source = |x: usize, y: usize| -> usize {
+        match degrees.rem_euclid(360) {
+            // the rotated image is height wide and width high
+            90 => (height - 1 - x) * width + y,
+            180 => (height - 1 - y) * width + (width - 1 - x),
+            270 => x * width + (width - 1 - y),
+            _ => y * width + x,
+        }
+    };
+    let (new_width, new_height) = match degrees.rem_euclid(360) {
+        0 | 180 => (width, height),
+        90 | 270 => (height, width),
+        _ => {
+            return Err(format!(
+                "Rotation should be a multiple of 90 degrees but {} given!",
+                degrees
+            ))
+        }
+    };
+
+    let mut rotated = Vec::with_capacity(pixels.len());
+    for y in 0..new_height {
+        for x in 0..new_width {
+            rotated.push(pixels[source(x, y)].clone());
+        }
+    }
+    Ok((rotated, new_width, new_height))
+}
+
+pub fn rotate(image: &mut PpmFile, degrees: i32) -> Result<(), String> {
+    let (pixels, width, height) = rotate_pixels(&image.pixels, image.width, image.height, degrees)?;
+    image.width = width;
+    image.height = height;
+    image.pixels = pixels;
+    Ok(())
+}
+
+// Mirrors rows of the given width left to right
+pub fn flip_horizontal_pixels<T>(pixels: &mut [T], width: usize) {
+    for row in pixels.chunks_mut(width.max(1)) {
+        row.reverse();
+    }
+}
+
+// Mirrors rows of the given width top to bottom
+pub fn flip_vertical_pixels<T>(pixels: &mut [T], width: usize) {
+    let height = pixels.len() / width.max(1);
+    for y in 0..height / 2 {
+        for x in 0..width {
+            pixels.swap(y * width + x, (height - 1 - y) * width + x);
+        }
+    }
+}
+
+pub fn flip_horizontal(image: &mut PpmFile) {
+    flip_horizontal_pixels(&mut image.pixels, image.width);
+}
+
+pub fn flip_vertical(image: &mut PpmFile) {
+    flip_vertical_pixels(&mut image.pixels, image.width);
+}
+
+// Bilinear sample at continuous coordinates where pixel centers lie at
+// i + 0.5, None outside of the image
+fn sample(image: &PpmFile, x: f32, y: f32) -> Option<Pixel> {
+    let x = x - 0.5;
+    let y = y - 0.5;
+    if x < -0.5 || y < -0.5 || x > image.width as f32 - 0.5 || y > image.height as f32 - 0.5 {
+        return None;
+    }
+    let x = x.clamp(0.0, (image.width - 1) as f32);
+    let y = y.clamp(0.0, (image.height - 1) as f32);
+    let x0 = x.floor() as usize;
+    let y0 = y.floor() as usize;
+    let x1 = usize::min(x0 + 1, image.width - 1);
+    let y1 = usize::min(y0 + 1, image.height - 1);
+    let fx = x - x0 as f32;
+    let fy = y - y0 as f32;
+    let mut pixel = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+    };
+    for &(px, py, weight) in &[
+        (x0, y0, (1.0 - fx) * (1.0 - fy)),
+        (x1, y0, fx * (1.0 - fy)),
+        (x0, y1, (1.0 - fx) * fy),
+        (x1, y1, fx * fy),
+    ] {
+        let p = &image.pixels[py * image.width + px];
+        pixel.r += p.r * weight;
+        pixel.g += p.g * weight;
+        pixel.b += p.b * weight;
+    }
+    Some(pixel)
+}
+
+// Fills every pixel from the source position the mapping gives for its
+// center, black where that falls outside of the image
+fn warp<F>(image: &mut PpmFile, map: F)
+where
+    F: Fn(f32, f32) -> (f32, f32),
+{
+    let source = image.clone();
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (sx, sy) = map(x as f32 + 0.5, y as f32 + 0.5);
+            image.pixels[y * image.width + x] = sample(&source, sx, sy).unwrap_or(Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            });
+        }
+    }
+}
+
+// Moves the pixel at (x, y) to (a*x + b*y + c, d*x + e*y + f)
+pub fn apply_affine(image: &mut PpmFile, matrix: &[f32]) -> Result<(), String> {
+    let (a, b, c, d, e, f) = match *matrix {
+        [a, b, c, d, e, f] => (a, b, c, d, e, f),
+        _ => {
+            return Err(format!(
+                "An affine transform needs 6 values but {} given!",
+                matrix.len()
+            ))
+        }
+    };
+    let det = a * e - b * d;
+    if det.abs() < f32::EPSILON {
+        return Err("The affine transform is not invertible!".to_string());
+    }
+    // the inverse maps every output pixel back onto the source
+    warp(image, |x, y| {
+        let x = x - c;
+        let y = y - f;
+        ((e * x - b * y) / det, (a * y - d * x) / det)
+    });
+    Ok(())
+}
+
+// Stretches the quadrilateral with the given top left, top right, bottom right
+// and bottom left corners over the whole image, e.g. to deskew a scanned page
+pub fn apply_perspective(image: &mut PpmFile, corners: &[f32]) -> Result<(), String> {
+    let (x0, y0, x1, y1, x2, y2, x3, y3) = match *corners {
+        [x0, y0, x1, y1, x2, y2, x3, y3] => (x0, y0, x1, y1, x2, y2, x3, y3),
+        _ => {
+            return Err(format!(
+                "A perspective transform needs 4 corners but {} values given!",
+                corners.len()
+            ))
+        }
+    };
+    // projective mapping of the unit square onto the quadrilateral (Heckbert)
+    let sx = x0 - x1 + x2 - x3;
+    let sy = y0 - y1 + y2 - y3;
+    let (g, h) = if sx.abs() < f32::EPSILON && sy.abs() < f32::EPSILON {
+        (0.0, 0.0)
+    } else {
+        let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+        let den = dx1 * dy2 - dx2 * dy1;
+        if den.abs() < f32::EPSILON {
+            return Err("The perspective corners are degenerate!".to_string());
+        }
+        ((sx * dy2 - dx2 * sy) / den, (dx1 * sy - sx * dy1) / den)
+    };
+    let (a, b, c) = (x1 - x0 + g * x1, x3 - x0 + h * x3, x0);
+    let (d, e, f) = (y1 - y0 + g * y1, y3 - y0 + h * y3, y0);
+
+    let width = image.width as f32;
+    let height = image.height as f32;
+    warp(image, |x, y| {
+        let u = x / width;
+        let v = y / height;
+        let w = g * u + h * v + 1.0;
+        ((a * u + b * v + c) / w, (d * u + e * v + f) / w)
+    });
+    Ok(())
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::color::{apply_levels, Levels};
+use crate::colorspace::{rgb_to_ycbcr, ycbcr_to_rgb};
+use crate::{luminance, save_ppm, to_byte, PpmFile};
+
+// Occurrences of every 8 bit RGB triplet present in the image
+fn rgb_histogram(image: &PpmFile) -> BTreeMap<(u8, u8, u8), u32> {
+    let mut counts = BTreeMap::new();
+    for pixel in &image.pixels {
+        *counts
+            .entry((to_byte(pixel.r), to_byte(pixel.g), to_byte(pixel.b)))
+            .or_insert(0) += 1;
+    }
+    counts
+}
+
+fn write_csv(counts: &BTreeMap<(u8, u8, u8), u32>, name: &str) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(name)?);
+    writeln!(file, "r,g,b,count")?;
+    for (&(r, g, b), count) in counts {
+        writeln!(file, "{},{},{},{}", r, g, b, count)?;
+    }
+    file.flush()
+}
+
+// ASCII point cloud, each vertex is positioned and colored by its RGB value
+fn write_ply(counts: &BTreeMap<(u8, u8, u8), u32>, name: &str) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(name)?);
+    writeln!(file, "ply")?;
+    writeln!(file, "format ascii 1.0")?;
+    writeln!(file, "element vertex {}", counts.len())?;
+    for property in &["x", "y", "z"] {
+        writeln!(file, "property float {}", property)?;
+    }
+    for property in &["red", "green", "blue"] {
+        writeln!(file, "property uchar {}", property)?;
+    }
+    writeln!(file, "property uint count")?;
+    writeln!(file, "end_header")?;
+    for (&(r, g, b), count) in counts {
+        writeln!(
+            file,
+            "{} {} {} {} {} {} {}",
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            r,
+            g,
+            b,
+            count
+        )?;
+    }
+    file.flush()
+}
+
+// 256*256 image of the histogram summed along one axis, log scaled
+fn projection<F>(counts: &BTreeMap<(u8, u8, u8), u32>, axes: F) -> PpmFile
+where
+    F: Fn(u8, u8, u8) -> (u8, u8),
+{
+    let mut sums = vec![0u64; 256 * 256];
+    for (&(r, g, b), &count) in counts {
+        let (x, y) = axes(r, g, b);
+        sums[(255 - y as usize) * 256 + x as usize] += count as u64;
+    }
+
+    let max = *sums.iter().max().unwrap_or(&0) as f32;
+    let mut image = PpmFile::new(256, 256).unwrap();
+    for (pixel, sum) in image.pixels.iter_mut().zip(sums) {
+        let val = if max > 0.0 {
+            (1.0 + sum as f32).ln() / (1.0 + max).ln()
+        } else {
+            0.0
+        };
+        pixel.r = val;
+        pixel.g = val;
+        pixel.b = val;
+    }
+    image
+}
+
+// The format follows the extension: .csv, .ply or, for anything else,
+// three projection images named <stem>_rg.ppm, <stem>_rb.ppm and <stem>_gb.ppm
+pub fn export_histogram3d(image: &PpmFile, name: &str) -> std::io::Result<()> {
+    let counts = rgb_histogram(image);
+    let path = Path::new(name);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => write_csv(&counts, name),
+        Some("ply") => write_ply(&counts, name),
+        _ => {
+            let stem = path.with_extension("");
+            let stem = stem.to_string_lossy();
+            save_ppm(
+                &projection(&counts, |r, g, _| (r, g)),
+                &format!("{}_rg.ppm", stem),
+            )?;
+            save_ppm(
+                &projection(&counts, |r, _, b| (r, b)),
+                &format!("{}_rb.ppm", stem),
+            )?;
+            save_ppm(
+                &projection(&counts, |_, g, b| (g, b)),
+                &format!("{}_gb.ppm", stem),
+            )
+        }
+    }
+}
+
+const BARS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// Luminance histogram as a columns*rows chart of Unicode block characters
+pub fn render_luminance_histogram(image: &PpmFile, columns: usize, rows: usize) -> String {
+    let mut bins = vec![0u32; columns];
+    for pixel in &image.pixels {
+        let lum = luminance(pixel).clamp(0.0, 1.0);
+        bins[usize::min((lum * columns as f32) as usize, columns - 1)] += 1;
+    }
+
+    let max = *bins.iter().max().unwrap_or(&0) as f32;
+    // bar heights in eighths of a row
+    let heights: Vec<usize> = bins
+        .iter()
+        .map(|&count| {
+            if max > 0.0 {
+                (count as f32 / max * (rows * 8) as f32).round() as usize
+            } else {
+                0
+            }
+        })
+        .collect();
+
+    let mut chart = String::new();
+    for row in (0..rows).rev() {
+        for &height in &heights {
+            chart.push(BARS[height.saturating_sub(row * 8).min(8)]);
+        }
+        chart.push('\n');
+    }
+    chart.push_str(&"─".repeat(columns));
+    chart
+}
+
+fn level(val: f32) -> usize {
+    (val.clamp(0.0, 1.0) * 255.0).round() as usize
+}
+
+// Spreads the luminance histogram evenly, the chroma is left untouched
+pub fn apply_equalization(image: &mut PpmFile) {
+    let mut histogram = [0usize; 256];
+    for pixel in &image.pixels {
+        histogram[level(luminance(pixel))] += 1;
+    }
+
+    let mut cdf = [0usize; 256];
+    let mut total = 0;
+    for (i, count) in histogram.iter().enumerate() {
+        total += count;
+        cdf[i] = total;
+    }
+    let cdf_min = cdf.iter().cloned().find(|&c| c > 0).unwrap_or(0);
+    if total == cdf_min {
+        // a single luminance level, nothing to spread
+        return;
+    }
+
+    for pixel in &mut image.pixels {
+        let (y, cb, cr) = rgb_to_ycbcr(pixel);
+        let equalized = (cdf[level(y)] - cdf_min) as f32 / (total - cdf_min) as f32;
+        *pixel = ycbcr_to_rgb(equalized, cb, cr);
+    }
+}
+
+// Stretches the values so the darkest low percent of the samples turn black
+// and the brightest high percent white. All channels share the stretch, which
+// keeps the hues.
+pub fn apply_autocontrast(image: &mut PpmFile, low: f32, high: f32) -> Result<(), String> {
+    if low < 0.0 || high < 0.0 || low + high >= 100.0 {
+        return Err(format!(
+            "Autocontrast clips percentages that add up to less than 100 but {} and {} given!",
+            low, high
+        ));
+    }
+    let mut histogram = [0usize; 256];
+    for pixel in &image.pixels {
+        for val in &[pixel.r, pixel.g, pixel.b] {
+            histogram[level(*val)] += 1;
+        }
+    }
+
+    let total = image.pixels.len() * 3;
+    // the first level below which more than the given share of the samples lie
+    let cut = |share: f32, levels: &mut dyn Iterator<Item = usize>| -> usize {
+        let limit = (total as f32 * share / 100.0) as usize;
+        let mut seen = 0;
+        for i in levels {
+            seen += histogram[i];
+            if seen > limit {
+                return i;
+            }
+        }
+        0
+    };
+    let black = cut(low, &mut (0..256));
+    let white = cut(high, &mut (0..256).rev());
+    if white <= black {
+        // a single level, nothing to stretch
+        return Ok(());
+    }
+
+    let levels = Levels {
+        in_black: black as f32 / 255.0,
+        in_white: white as f32 / 255.0,
+        gamma: 1.0,
+        out_black: 0.0,
+        out_white: 1.0,
+    };
+    apply_levels(image, &levels, [true, true, true]);
+    Ok(())
+}
+
+// Tiled histogram equalization of the luminance. Every tile histogram is
+// clipped at clip_limit times its average bin count and the excess spread over
+// all bins, then the mappings of the four closest tiles are interpolated.
+pub fn apply_clahe(image: &mut PpmFile, clip_limit: f32, grid: usize) -> Result<(), String> {
+    if grid == 0 || clip_limit < 1.0 {
+        return Err(format!(
+            "CLAHE needs a positive grid and a clip limit of at least 1 but {} and {} given!",
+            grid, clip_limit
+        ));
+    }
+    let width = image.width;
+    let height = image.height;
+    let tile_w = usize::max(width.div_ceil(grid), 1);
+    let tile_h = usize::max(height.div_ceil(grid), 1);
+    let tiles_x = width.div_ceil(tile_w);
+    let tiles_y = height.div_ceil(tile_h);
+    let ycbcr: Vec<(f32, f32, f32)> = image.pixels.iter().map(rgb_to_ycbcr).collect();
+
+    let mut mappings: Vec<[f32; 256]> = Vec::with_capacity(tiles_x * tiles_y);
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let mut histogram = [0.0f32; 256];
+            let mut count = 0;
+            for y in ty * tile_h..usize::min((ty + 1) * tile_h, height) {
+                for x in tx * tile_w..usize::min((tx + 1) * tile_w, width) {
+                    histogram[level(ycbcr[y * width + x].0)] += 1.0;
+                    count += 1;
+                }
+            }
+
+            let limit = clip_limit * count as f32 / 256.0;
+            let mut excess = 0.0;
+            for bin in histogram.iter_mut() {
+                if *bin > limit {
+                    excess += *bin - limit;
+                    *bin = limit;
+                }
+            }
+            let mut mapping = [0.0f32; 256];
+            let mut total = 0.0;
+            for (i, bin) in histogram.iter().enumerate() {
+                total += bin + excess / 256.0;
+                mapping[i] = total / count as f32;
+            }
+            mappings.push(mapping);
+        }
+    }
+
+    // position relative to the tile centers, clamped at the outer half tiles
+    let locate = |pos: usize, size: usize, tiles: usize| {
+        let t = ((pos as f32 + 0.5) / size as f32 - 0.5).clamp(0.0, (tiles - 1) as f32);
+        let first = usize::min(t as usize, tiles.saturating_sub(2));
+        let second = usize::min(first + 1, tiles - 1);
+        (first, second, t - first as f32)
+    };
+    for y in 0..height {
+        let (y0, y1, fy) = locate(y, tile_h, tiles_y);
+        for x in 0..width {
+            let (x0, x1, fx) = locate(x, tile_w, tiles_x);
+            let (lum, cb, cr) = ycbcr[y * width + x];
+            let bin = level(lum);
+            let top = mappings[y0 * tiles_x + x0][bin] * (1.0 - fx)
+                + mappings[y0 * tiles_x + x1][bin] * fx;
+            let bottom = mappings[y1 * tiles_x + x0][bin] * (1.0 - fx)
+                + mappings[y1 * tiles_x + x1][bin] * fx;
+            image.pixels[y * width + x] = ycbcr_to_rgb(top * (1.0 - fy) + bottom * fy, cb, cr);
+        }
+    }
+    Ok(())
+}
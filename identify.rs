@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+
+use crate::format::{detect_format, Format};
+use crate::pam;
+use crate::{header_comments, header_number, next_token, PpmError};
+
+// Headers longer than this are not worth reading
+const MAX_HEADER: u64 = 64 * 1024;
+
+// Prints what the header of a NetPBM or PFM file says without reading the
+// pixels: format, size, maximum value, comments and the size of the pixel data
+pub fn identify(file: &str) -> Result<(), Box<dyn Error>> {
+    let mut handle = File::open(file)?;
+    let file_len = handle.metadata()?.len();
+    let mut bytes = Vec::new();
+    (&mut handle).take(MAX_HEADER).read_to_end(&mut bytes)?;
+
+    let format = match detect_format(&bytes) {
+        Some(format) => format,
+        None => {
+            println!("{}: unknown format", file);
+            return Ok(());
+        }
+    };
+    println!("{}: {}", file, format);
+    if let Format::Pam = format {
+        return identify_pam(&bytes, file_len);
+    }
+    let header = match format {
+        Format::PbmAscii
+        | Format::PgmAscii
+        | Format::PpmAscii
+        | Format::PbmBinary
+        | Format::PgmBinary
+        | Format::PpmBinary
+        | Format::PfmColor
+        | Format::PfmGray => &bytes,
+        // no header this tool knows how to read
+        _ => return Ok(()),
+    };
+
+    let mut offset = 0;
+    next_token(header, &mut offset)?;
+    let width = header_number(header, &mut offset, "width")?;
+    let height = header_number(header, &mut offset, "height")?;
+    println!("  size: {}x{}", width, height);
+
+    // bytes per sample of the binary formats, None for ASCII data
+    let sample_size = match format {
+        Format::PfmColor | Format::PfmGray => {
+            let scale = next_token(header, &mut offset)?;
+            let scale = scale
+                .parse::<f32>()
+                .map_err(|_| PpmError::Malformed(format!("PFM scale not a number: {}", scale)))?;
+            let order = if scale < 0.0 {
+                "little endian"
+            } else {
+                "big endian"
+            };
+            println!("  scale: {} ({})", scale.abs(), order);
+            Some(4)
+        }
+        Format::PbmAscii | Format::PbmBinary => None,
+        _ => {
+            let max_val = header_number(header, &mut offset, "max color value")?;
+            println!("  max value: {}", max_val);
+            match format {
+                Format::PgmBinary | Format::PpmBinary if max_val > 255 => Some(2),
+                Format::PgmBinary | Format::PpmBinary => Some(1),
+                _ => None,
+            }
+        }
+    };
+    for comment in header_comments(&header[..offset]) {
+        println!("  comment: {}", comment);
+    }
+
+    // the single whitespace ending the header
+    let data = file_len.saturating_sub(offset as u64 + 1);
+    let pixels = (width as u64).saturating_mul(height as u64);
+    let expected = match (format, sample_size) {
+        (Format::PbmBinary, _) => Some((width.div_ceil(8) as u64).saturating_mul(height as u64)),
+        (Format::PgmBinary, Some(size)) | (Format::PfmGray, Some(size)) => {
+            Some(pixels.saturating_mul(size))
+        }
+        (_, Some(size)) => Some(pixels.saturating_mul(3 * size)),
+        _ => None,
+    };
+    match expected {
+        Some(expected) => println!("  pixel data: {} bytes ({} expected)", data, expected),
+        None => println!("  pixel data: {} bytes", data),
+    }
+    Ok(())
+}
+
+// PAM lists its fields by name, the tuple type says what the samples mean
+fn identify_pam(bytes: &[u8], file_len: u64) -> Result<(), Box<dyn Error>> {
+    let header = pam::read_header(bytes)?;
+    println!("  size: {}x{}", header.width, header.height);
+    println!("  depth: {}", header.depth);
+    println!("  max value: {}", header.max_val);
+    if !header.tuple_type.is_empty() {
+        println!("  tuple type: {}", header.tuple_type);
+    }
+    for comment in header_comments(&bytes[..header.data]) {
+        println!("  comment: {}", comment);
+    }
+    let sample_size = if header.max_val > 255 { 2 } else { 1 };
+    let expected = (header.width as u64)
+        .saturating_mul(header.height as u64)
+        .saturating_mul(header.depth as u64)
+        .saturating_mul(sample_size);
+    println!(
+        "  pixel data: {} bytes ({} expected)",
+        file_len.saturating_sub(header.data as u64),
+        expected
+    );
+    Ok(())
+}
@@ -0,0 +1,435 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::parallel::{self, for_each_row};
+use crate::{Pixel, PpmFile};
+
+// Odd sized kernel with its weights stored row by row
+pub struct Kernel {
+    pub width: usize,
+    pub height: usize,
+    pub weights: Vec<f32>,
+    pub bias: f32,
+}
+
+impl Kernel {
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Result<Kernel, String> {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(format!(
+                "Kernel dimensions should be odd but {}x{} given!",
+                width, height
+            ));
+        }
+        if weights.len() != width * height {
+            return Err(format!(
+                "A {}x{} kernel needs {} weights but {} given!",
+                width,
+                height,
+                width * height,
+                weights.len()
+            ));
+        }
+        Ok(Kernel {
+            width,
+            height,
+            weights,
+            bias: 0.0,
+        })
+    }
+
+    // Normalized size*size gaussian, size should be odd
+    pub fn gaussian(size: usize, sigma: f32) -> Result<Kernel, String> {
+        let center = (size / 2) as f32;
+        let mut weights = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                let dx = x as f32 - center;
+                let dy = y as f32 - center;
+                weights.push(f32::exp(-(dx * dx + dy * dy) / (2.0 * sigma * sigma)));
+            }
+        }
+        Ok(Kernel::new(size, size, weights)?.normalized())
+    }
+
+    // Gaussian reaching out to three sigmas on each side
+    pub fn gaussian_sigma(sigma: f32) -> Result<Kernel, String> {
+        if sigma <= 0.0 {
+            return Err(format!("Sigma should be positive but {} given!", sigma));
+        }
+        Kernel::gaussian(2 * (3.0 * sigma).ceil() as usize + 1, sigma)
+    }
+
+    // Normalized line of the given length through the center, at an angle in
+    // degrees counter-clockwise from the x axis
+    pub fn motion(length: f32, angle: f32) -> Result<Kernel, String> {
+        if length < 1.0 {
+            return Err(format!(
+                "Motion blur needs a length of at least 1 but {} given!",
+                length
+            ));
+        }
+        let size = 2 * (length / 2.0).ceil() as usize + 1;
+        let center = (size / 2) as f32;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut weights = vec![0.0f32; size * size];
+        // a sample every quarter pixel, splatted bilinearly
+        let samples = (length * 4.0).ceil() as usize;
+        for i in 0..=samples {
+            let t = i as f32 / samples as f32 * length - length / 2.0;
+            let x = center + t * cos;
+            let y = center - t * sin;
+            let (x0, y0) = (x.floor(), y.floor());
+            let (fx, fy) = (x - x0, y - y0);
+            for &(dx, dy, weight) in &[
+                (0, 0, (1.0 - fx) * (1.0 - fy)),
+                (1, 0, fx * (1.0 - fy)),
+                (0, 1, (1.0 - fx) * fy),
+                (1, 1, fx * fy),
+            ] {
+                let kx = x0 as isize + dx;
+                let ky = y0 as isize + dy;
+                if kx >= 0 && ky >= 0 && (kx as usize) < size && (ky as usize) < size {
+                    weights[ky as usize * size + kx as usize] += weight;
+                }
+            }
+        }
+        Ok(Kernel::new(size, size, weights)?.normalized())
+    }
+
+    // Rows are separated by `;` or new lines and weights by `,` or whitespace,
+    // e.g. "0,-1,0;-1,5,-1;0,-1,0". The result is normalized unless a
+    // `normalize=false` row says otherwise, and a `bias=<value>` row adds the
+    // value to every result, e.g. "-1,0,1;-2,0,2;-1,0,1;bias=0.5".
+    pub fn parse(spec: &str) -> Result<Kernel, String> {
+        let mut rows: Vec<Vec<f32>> = Vec::new();
+        let mut bias = 0.0;
+        let mut normalize = true;
+        for row in spec.split([';', '\n']) {
+            if let Some((option, value)) = row.split_once('=') {
+                let value = value.trim();
+                match option.trim() {
+                    "bias" => {
+                        bias = value
+                            .parse::<f32>()
+                            .map_err(|error| format!("Bad kernel bias {}: {}", value, error))?
+                    }
+                    "normalize" => {
+                        normalize = value.parse::<bool>().map_err(|_| {
+                            format!("Expected normalize=true or false but {} given!", value)
+                        })?
+                    }
+                    option => return Err(format!("Unknown kernel option: {}", option)),
+                }
+                continue;
+            }
+            let mut weights = Vec::new();
+            for weight in row.split(|c: char| c == ',' || c.is_whitespace()) {
+                if weight.is_empty() {
+                    continue;
+                }
+                weights.push(
+                    weight
+                        .parse::<f32>()
+                        .map_err(|error| format!("Bad kernel weight {}: {}", weight, error))?,
+                );
+            }
+            if !weights.is_empty() {
+                rows.push(weights);
+            }
+        }
+
+        let width = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(format!("Kernel rows differ in length: {}", spec.trim()));
+        }
+        let height = rows.len();
+        let mut kernel = Kernel::new(width, height, rows.concat())?;
+        if normalize {
+            kernel = kernel.normalized();
+        }
+        kernel.bias = bias;
+        Ok(kernel)
+    }
+
+    // Scales the weights so that they sum up to one. Kernels summing up to
+    // zero (e.g. edge detectors) are left untouched.
+    pub fn normalized(mut self) -> Kernel {
+        let sum: f32 = self.weights.iter().sum();
+        if sum.abs() > f32::EPSILON {
+            for weight in &mut self.weights {
+                *weight /= sum;
+            }
+        }
+        self
+    }
+}
+
+// What neighbours outside of the image are taken to be
+#[derive(Clone, Copy, Debug)]
+pub enum Border {
+    Constant(f32),
+    // the closest edge pixel
+    Replicate,
+    // mirrored at the edge pixel, which isn't repeated
+    Reflect,
+    // the opposite side of the image
+    Wrap,
+}
+
+impl Border {
+    // `constant` (black), `constant,<value>`, `replicate`, `reflect` or `wrap`
+    pub fn parse(spec: &str) -> Result<Border, String> {
+        match spec.split_once(',') {
+            Some(("constant", value)) => value
+                .trim()
+                .parse::<f32>()
+                .map(Border::Constant)
+                .map_err(|error| format!("Bad border value {}: {}", value, error)),
+            None if spec == "constant" => Ok(Border::Constant(0.0)),
+            None if spec == "replicate" => Ok(Border::Replicate),
+            None if spec == "reflect" => Ok(Border::Reflect),
+            None if spec == "wrap" => Ok(Border::Wrap),
+            _ => Err(format!("Unknown border mode: {}", spec)),
+        }
+    }
+
+    // Index within 0..len standing in for pos, None for a constant border
+    fn index(self, pos: isize, len: usize) -> Option<usize> {
+        let len = len as isize;
+        if (0..len).contains(&pos) {
+            return Some(pos as usize);
+        }
+        match self {
+            Border::Constant(_) => None,
+            Border::Replicate => Some(pos.clamp(0, len - 1) as usize),
+            Border::Reflect if len == 1 => Some(0),
+            Border::Reflect => {
+                // the reflection repeats every 2 * (len - 1) pixels
+                let period = 2 * (len - 1);
+                let pos = pos.rem_euclid(period);
+                Some(if pos < len { pos } else { period - pos } as usize)
+            }
+            Border::Wrap => Some(pos.rem_euclid(len) as usize),
+        }
+    }
+}
+
+// Applies the kernel as it is laid out (no flipping) to a single channel,
+// neighbours outside of the image follow the border mode
+pub fn convolve_channel(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    kernel: &Kernel,
+    border: Border,
+) -> Vec<f32> {
+    let rx = (kernel.width / 2) as isize;
+    let ry = (kernel.height / 2) as isize;
+    let constant = match border {
+        Border::Constant(value) => value,
+        _ => 0.0,
+    };
+    let mut out = vec![0.0f32; values.len()];
+    for_each_row(&mut out, width, |y, row| {
+        for (x, out) in row.iter_mut().enumerate() {
+            let mut val = kernel.bias;
+            for ky in 0..kernel.height {
+                let row = border.index(y as isize + ky as isize - ry, height);
+                for kx in 0..kernel.width {
+                    let weight = kernel.weights[ky * kernel.width + kx];
+                    let column = border.index(x as isize + kx as isize - rx, width);
+                    val += weight
+                        * match (row, column) {
+                            (Some(row), Some(column)) => values[row * width + column],
+                            _ => constant,
+                        };
+                }
+            }
+            *out = val;
+        }
+    });
+    out
+}
+
+// Convolves every channel of the image in place. Each band of rows only keeps
+// aside the source rows it still needs once they have been overwritten, so
+// the extra memory grows with the width instead of the whole image.
+pub fn convolve(image: &mut PpmFile, kernel: &Kernel, border: Border) {
+    let width = image.width;
+    let height = image.height;
+    if width == 0 || height == 0 {
+        return;
+    }
+    let rx = (kernel.width / 2) as isize;
+    let ry = kernel.height / 2;
+    let constant = match border {
+        Border::Constant(value) => value,
+        _ => 0.0,
+    };
+    // source row of every kernel row, None for a constant border
+    let source_rows = |y: usize| {
+        (0..kernel.height)
+            .map(move |ky| border.index(y as isize + ky as isize - ry as isize, height))
+    };
+
+    // the rows a band reads that belong to another band, or that it overwrote
+    // more than ry rows earlier, are copied before any band starts
+    let band = parallel::band_height(height);
+    let saved: Vec<BTreeMap<usize, Vec<Pixel>>> = (0..height.div_ceil(band))
+        .map(|i| {
+            let rows = i * band..usize::min((i + 1) * band, height);
+            let mut saved = BTreeMap::new();
+            for y in rows.clone() {
+                for sy in source_rows(y).flatten() {
+                    if !rows.contains(&sy) || sy + ry < y {
+                        saved
+                            .entry(sy)
+                            .or_insert_with(|| image.pixels[sy * width..(sy + 1) * width].to_vec());
+                    }
+                }
+            }
+            saved
+        })
+        .collect();
+
+    parallel::for_each_band(&mut image.pixels, width, band, |first, pixels| {
+        let saved = &saved[first / band];
+        // the original rows max(first, y - ry)..y
+        let mut ring: VecDeque<Vec<Pixel>> = VecDeque::with_capacity(ry + 1);
+        let mut out = vec![
+            Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0
+            };
+            width
+        ];
+        for local in 0..pixels.len() / width {
+            let y = first + local;
+            let ring_start = y - ring.len();
+            let sources: Vec<Option<&[Pixel]>> = source_rows(y)
+                .map(|sy| {
+                    sy.map(|sy| match saved.get(&sy) {
+                        Some(row) => row.as_slice(),
+                        None if sy < y => ring[sy - ring_start].as_slice(),
+                        None => &pixels[(sy - first) * width..(sy - first + 1) * width],
+                    })
+                })
+                .collect();
+            for (x, out) in out.iter_mut().enumerate() {
+                out.r = kernel.bias;
+                out.g = kernel.bias;
+                out.b = kernel.bias;
+                for (ky, row) in sources.iter().enumerate() {
+                    for kx in 0..kernel.width {
+                        let weight = kernel.weights[ky * kernel.width + kx];
+                        match (row, border.index(x as isize + kx as isize - rx, width)) {
+                            (Some(row), Some(column)) => {
+                                out.r += weight * row[column].r;
+                                out.g += weight * row[column].g;
+                                out.b += weight * row[column].b;
+                            }
+                            _ => {
+                                out.r += weight * constant;
+                                out.g += weight * constant;
+                                out.b += weight * constant;
+                            }
+                        }
+                    }
+                }
+            }
+
+            let row = &mut pixels[local * width..(local + 1) * width];
+            if ry > 0 {
+                // reuse the allocation of the row leaving the ring
+                let mut original = match ring.len() {
+                    len if len == ry => ring.pop_front().unwrap(),
+                    _ => Vec::with_capacity(width),
+                };
+                original.clear();
+                original.extend_from_slice(row);
+                ring.push_back(original);
+            }
+            row.clone_from_slice(&out);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize, values: &[f32]) -> PpmFile {
+        let mut image = PpmFile::new(width, height).unwrap();
+        for (pixel, &val) in image.pixels.iter_mut().zip(values) {
+            *pixel = Pixel {
+                r: val,
+                g: val,
+                b: val,
+            };
+        }
+        image
+    }
+
+    #[test]
+    fn parse_normalizes_by_default() {
+        let kernel = Kernel::parse("1,1,1;1,1,1;1,1,1").unwrap();
+        assert_eq!((kernel.width, kernel.height), (3, 3));
+        assert!(kernel.weights.iter().all(|&w| (w - 1.0 / 9.0).abs() < 1e-6));
+        assert_eq!(kernel.bias, 0.0);
+    }
+
+    #[test]
+    fn parse_reads_options() {
+        let kernel = Kernel::parse("1 1 1\n1 1 1\n1 1 1\nnormalize=false\nbias=0.25").unwrap();
+        assert!(kernel.weights.iter().all(|&w| w == 1.0));
+        assert_eq!(kernel.bias, 0.25);
+    }
+
+    #[test]
+    fn parse_rejects_bad_specs() {
+        assert!(Kernel::parse("1,1;1,1").is_err());
+        assert!(Kernel::parse("1,1,1;1,1").is_err());
+        assert!(Kernel::parse("1,x,1").is_err());
+        assert!(Kernel::parse("1;bias=high").is_err());
+        assert!(Kernel::parse("1;normalize=maybe").is_err());
+        assert!(Kernel::parse("1;scale=2").is_err());
+    }
+
+    #[test]
+    fn zero_sum_kernels_stay_unnormalized() {
+        let kernel = Kernel::parse("-1,0,1").unwrap();
+        assert_eq!(kernel.weights, vec![-1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn identity_keeps_the_image() {
+        let values = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6];
+        let mut out = image(3, 2, &values);
+        convolve(
+            &mut out,
+            &Kernel::parse("0,0,0;0,1,0;0,0,0").unwrap(),
+            Border::Reflect,
+        );
+        for (pixel, &val) in out.pixels.iter().zip(&values) {
+            assert!((pixel.r - val).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn bias_is_added_to_every_result() {
+        let kernel = Kernel::parse("0,0,0;0,1,0;0,0,0;bias=0.5").unwrap();
+        let out = convolve_channel(&[0.0, 0.25], 2, 1, &kernel, Border::Replicate);
+        assert_eq!(out, vec![0.5, 0.75]);
+    }
+
+    #[test]
+    fn borders_stand_in_for_outside_pixels() {
+        let kernel = Kernel::parse("1,0,0;normalize=false").unwrap();
+        let values = [1.0, 2.0, 3.0];
+        let left = |border| convolve_channel(&values, 3, 1, &kernel, border)[0];
+        assert_eq!(left(Border::Constant(0.5)), 0.5);
+        assert_eq!(left(Border::Replicate), 1.0);
+        assert_eq!(left(Border::Reflect), 2.0);
+        assert_eq!(left(Border::Wrap), 3.0);
+    }
+}
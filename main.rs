@@ -1,36 +1,127 @@
 use std::env;
 use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
+use std::mem;
 use std::path::Path;
-use std::string::FromUtf8Error;
-
-fn next_token(
-    bytes: &Vec<u8>,
-    offset: &mut usize,
-    delims: &Vec<u8>,
-) -> Result<String, FromUtf8Error> {
-    // skip depims and comments
-    while delims.contains(&bytes[*offset]) {
-        // skip the entire line in case of comments
-        if bytes[*offset] == 0x23 {
-            *offset += 1;
-            while bytes[*offset] != 0x0A {
-                *offset += 1;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+mod bench;
+mod channels;
+mod color;
+mod colormap;
+mod colorspace;
+mod compare;
+mod compose;
+mod contour;
+mod denoise;
+mod dither;
+mod draw;
+mod fixtures;
+mod font;
+mod format;
+mod fx;
+mod generate;
+mod geometry;
+mod histogram;
+mod identify;
+mod kernel;
+mod morphology;
+mod mosaic;
+mod noise;
+mod packed;
+mod palette;
+mod pam;
+mod parallel;
+mod pfm;
+mod pipe;
+mod quantize;
+mod rng;
+mod scale;
+mod scopes;
+mod serve;
+mod similar;
+mod stats;
+mod stylize;
+mod term;
+mod texture;
+mod threshold;
+mod tiled;
+mod timing;
+mod tonemap;
+
+use format::{detect_format, Format};
+use kernel::{convolve, convolve_channel, Border, Kernel};
+use packed::PackedImage;
+
+// Whitespace as the NetPBM specification defines it
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r' | 0x0B | 0x0C)
+}
+
+// Next header field starting at *offset. Comments run from a `#` to the end of
+// the line and may appear wherever whitespace may, also right after a field.
+fn next_token<'a>(bytes: &'a [u8], offset: &mut usize) -> Result<&'a str, PpmError> {
+    loop {
+        match bytes.get(*offset) {
+            Some(b'#') => {
+                while bytes
+                    .get(*offset)
+                    .is_some_and(|&b| b != b'\n' && b != b'\r')
+                {
+                    *offset += 1;
+                }
             }
+            Some(&b) if is_space(b) => *offset += 1,
+            Some(_) => break,
+            None => return Err(PpmError::Malformed("header ends early".to_string())),
         }
-        *offset += 1;
     }
 
-    let from: usize = *offset;
-    for byte in &bytes[from..] {
-        if delims.contains(byte) {
-            break;
-        }
+    let from = *offset;
+    while bytes
+        .get(*offset)
+        .is_some_and(|&b| !is_space(b) && b != b'#')
+    {
         *offset += 1;
     }
-    String::from_utf8(bytes[from..*offset].to_vec())
+    std::str::from_utf8(&bytes[from..*offset])
+        .map_err(|_| PpmError::Malformed("header is not ASCII".to_string()))
+}
+
+// Text of every comment of the header, a `#` can only start a comment there
+fn header_comments(header: &[u8]) -> Vec<String> {
+    let mut comments = Vec::new();
+    let mut rest = header;
+    while let Some(start) = rest.iter().position(|&b| b == b'#') {
+        rest = &rest[start + 1..];
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n' || b == b'\r')
+            .unwrap_or(rest.len());
+        let text = String::from_utf8_lossy(&rest[..end]);
+        // the space after the `#` is a convention rather than part of the text
+        comments.push(
+            text.strip_prefix(' ')
+                .unwrap_or(&text)
+                .trim_end()
+                .to_string(),
+        );
+        rest = &rest[end..];
+    }
+    comments
+}
+
+// Unsigned decimal header field
+fn header_number(bytes: &[u8], offset: &mut usize, name: &str) -> Result<usize, PpmError> {
+    let token = next_token(bytes, offset)?;
+    token
+        .parse::<usize>()
+        .map_err(|_| PpmError::Malformed(format!("{} not a number: {}", name, token)))
 }
 
 #[derive(Clone)]
@@ -40,194 +131,1302 @@ struct Pixel {
     b: f32,
 }
 
+#[derive(Clone)]
 struct PpmFile {
     width: usize,
     height: usize,
     max_val: usize,
     pixels: Vec<Pixel>,
+    // header comments without their `#`, written back on save
+    comments: Vec<String>,
+    // opacity of every pixel from 0 to 1, None for opaque images
+    alpha: Option<Vec<f32>>,
 }
 
-fn parse_ppm(file: &str) -> Result<PpmFile, String> {
-    let bytes: Vec<u8> =
-        fs::read(file).unwrap_or_else(|error| panic!("Could not read file: {}", error));
+// Single channel image, e.g. a luminance or an edge map
+struct GrayImage {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
 
-    if bytes.len() < 2 {
-        return Err(format!("PPM file too small!"));
+impl GrayImage {
+    fn from_luminance(image: &PpmFile) -> GrayImage {
+        GrayImage {
+            width: image.width,
+            height: image.height,
+            values: image.pixels.iter().map(luminance).collect(),
+        }
     }
 
-    let mut from = 0;
-    let delims: Vec<u8> = vec![0x20, 0x09, 0x0D, 0x0A, 0x23];
+    fn to_ppm(&self) -> PpmFile {
+        PpmFile {
+            width: self.width,
+            height: self.height,
+            max_val: 255,
+            comments: Vec::new(),
+            alpha: None,
+            pixels: self
+                .values
+                .iter()
+                .map(|&val| Pixel {
+                    r: val,
+                    g: val,
+                    b: val,
+                })
+                .collect(),
+        }
+    }
+}
 
-    let magic_number = next_token(&bytes, &mut from, &delims)
-        .unwrap_or_else(|error| panic!("Magic number: {}", error));
+#[derive(Debug)]
+enum PpmError {
+    Io(std::io::Error),
+    TooSmall,
+    UnknownFormat,
+    // recognized, but not something this tool decodes
+    UnsupportedFormat(Format),
+    // width*height (or the bytes needed to hold it) does not fit in a usize
+    DimensionOverflow { width: usize, height: usize },
+    // fewer bytes of pixel data than the header promises
+    Truncated { expected: usize, found: usize },
+    Malformed(String),
+}
 
-    let width = next_token(&bytes, &mut from, &delims)
-        .unwrap_or_else(|error| panic!("Could not read width: {}", error))
-        .parse::<usize>()
-        .unwrap_or_else(|error| panic!("Width not a number: {}", error));
+impl fmt::Display for PpmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PpmError::Io(error) => write!(f, "Could not read file: {}", error),
+            PpmError::TooSmall => write!(f, "PPM file too small!"),
+            PpmError::UnknownFormat => write!(f, "Unknown image format!"),
+            PpmError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported image format: {}", format)
+            }
+            PpmError::DimensionOverflow { width, height } => {
+                write!(f, "Image dimensions {}x{} are too large!", width, height)
+            }
+            PpmError::Truncated { expected, found } => write!(
+                f,
+                "Pixel data is truncated: expected {} bytes but {} found!",
+                expected, found
+            ),
+            PpmError::Malformed(reason) => write!(f, "Malformed image: {}", reason),
+        }
+    }
+}
 
-    let height = next_token(&bytes, &mut from, &delims)
-        .unwrap_or_else(|error| panic!("Could not read height: {}", error))
-        .parse::<usize>()
-        .unwrap_or_else(|error| panic!("Height not a number: {}", error));
+impl Error for PpmError {}
 
-    let max_color_val = next_token(&bytes, &mut from, &delims)
-        .unwrap_or_else(|error| panic!("Could not read max color value: {}", error))
-        .parse::<usize>()
-        .unwrap_or_else(|error| panic!("Max color value not a number: {}", error));
+// Number of pixels of a width*height image, checked for overflow
+fn pixel_count(width: usize, height: usize) -> Result<usize, PpmError> {
+    width
+        .checked_mul(height)
+        .ok_or(PpmError::DimensionOverflow { width, height })
+}
+
+// Largest image the steps allocate, 4 GiB of float pixels
+const MAX_IMAGE_BYTES: u64 = 1 << 32;
 
-    if magic_number != "P6" {
-        panic!("Unknown magic number: {}", magic_number);
+// Pixel count of a float image about to be allocated, checked against the
+// bytes it takes too so a huge size is an error rather than an aborting
+// allocation
+fn allocated_pixel_count(width: usize, height: usize) -> Result<usize, PpmError> {
+    let count = pixel_count(width, height)?;
+    match count.checked_mul(mem::size_of::<Pixel>()) {
+        Some(bytes) if bytes <= isize::MAX as usize && bytes as u64 <= MAX_IMAGE_BYTES => Ok(count),
+        _ => Err(PpmError::DimensionOverflow { width, height }),
     }
+}
+
+// Pixel count of an image read from a file, which needs at least one pixel
+fn decoded_pixel_count(width: usize, height: usize) -> Result<usize, PpmError> {
+    if width == 0 || height == 0 {
+        return Err(PpmError::Malformed(format!(
+            "the image is {}x{} and has no pixels",
+            width, height
+        )));
+    }
+    pixel_count(width, height)
+}
+
+impl PpmFile {
+    // A black image with an 8 bit color depth
+    fn new(width: usize, height: usize) -> Result<PpmFile, PpmError> {
+        Ok(PpmFile {
+            width,
+            height,
+            max_val: 255,
+            comments: Vec::new(),
+            alpha: None,
+            pixels: vec![
+                Pixel {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0
+                };
+                allocated_pixel_count(width, height)?
+            ],
+        })
+    }
+}
+
+impl PpmFile {
+    // Adds a comment line to the header, text spanning several lines becomes
+    // one comment per line
+    fn add_comment(&mut self, text: &str) {
+        self.comments
+            .extend(text.lines().map(|line| line.trim_end().to_string()));
+    }
+
+    // Copy of the width*height region starting at (x, y), which has to lie within the image
+    fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> PpmFile {
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in y..y + height {
+            pixels.extend_from_slice(
+                &self.pixels[row * self.width + x..row * self.width + x + width],
+            );
+        }
+        PpmFile {
+            width,
+            height,
+            max_val: self.max_val,
+            pixels,
+            comments: self.comments.clone(),
+            alpha: None,
+        }
+    }
+}
+
+// 8 bit inputs are kept packed until a step needs floating point pixels
+enum Loaded {
+    Packed(PackedImage),
+    Float(PpmFile),
+}
+
+fn parse_ppm(file: &str) -> Result<PpmFile, PpmError> {
+    match load_image(file)? {
+        Loaded::Packed(packed) => Ok(packed.unpack()),
+        Loaded::Float(image) => Ok(image),
+    }
+}
+
+fn load_image(file: &str) -> Result<Loaded, PpmError> {
+    let bytes = fs::read(file).map_err(PpmError::Io)?;
+    decode_image(&bytes)
+}
+
+// Never panics on malformed input, whatever the bytes every problem comes back
+// as an error
+fn decode_image(bytes: &[u8]) -> Result<Loaded, PpmError> {
+    if bytes.len() < 2 {
+        return Err(PpmError::TooSmall);
+    }
+
+    match detect_format(bytes) {
+        Some(Format::PpmBinary) => {}
+        Some(Format::PfmColor) | Some(Format::PfmGray) => {
+            return pfm::parse_pfm(bytes).map(Loaded::Float)
+        }
+        Some(Format::Pam) => return pam::parse_pam(bytes).map(Loaded::Packed),
+        Some(format) => return Err(PpmError::UnsupportedFormat(format)),
+        None => return Err(PpmError::UnknownFormat),
+    }
+
+    // the magic number was already checked by detect_format
+    let mut from = 0;
+    next_token(bytes, &mut from)?;
+    let width = header_number(bytes, &mut from, "width")?;
+    let height = header_number(bytes, &mut from, "height")?;
+    let max_color_val = header_number(bytes, &mut from, "max color value")?;
 
     if max_color_val != 255 {
-        panic!("Maximum color value is not 255!");
+        return Err(PpmError::Malformed(format!(
+            "only a maximum color value of 255 is supported but {} found",
+            max_color_val
+        )));
     }
 
-    // The last char should be whitespace
-    if bytes[from] == 0x23 || !delims.contains(&bytes[from]) {
-        panic!(
-            "The header should end with a whitespace but {} found!",
-            bytes[from]
-        );
+    // a single whitespace separates the header from the pixel data
+    match bytes.get(from) {
+        Some(&b) if is_space(b) => {}
+        Some(&b) => {
+            return Err(PpmError::Malformed(format!(
+                "the header should end with a whitespace but {} found",
+                b
+            )))
+        }
+        None => return Err(PpmError::Malformed("no pixel data".to_string())),
     }
 
     from += 1;
-    let mut pixels = Vec::new();
-    pixels.reserve(width * height);
-    for i in 0..(width * height) {
-        pixels.push(Pixel {
-            r: bytes[from + i * 3] as f32 / max_color_val as f32,
-            g: bytes[from + i * 3 + 1] as f32 / max_color_val as f32,
-            b: bytes[from + i * 3 + 2] as f32 / max_color_val as f32,
+    let len = decoded_pixel_count(width, height)?
+        .checked_mul(3)
+        .ok_or(PpmError::DimensionOverflow { width, height })?;
+    let found = bytes.len().saturating_sub(from);
+    if found < len {
+        return Err(PpmError::Truncated {
+            expected: len,
+            found,
         });
     }
+    if found > len {
+        eprintln!("Ignoring {} bytes after the pixel data", found - len);
+    }
+    let pixels = bytes[from..from + len]
+        .chunks_exact(3)
+        .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+        .collect();
 
-    Ok(PpmFile {
-        width: width,
-        height: height,
-        max_val: max_color_val,
-        pixels: pixels,
-    })
+    Ok(Loaded::Packed(PackedImage {
+        width,
+        height,
+        pixels,
+        alpha: None,
+        comments: header_comments(&bytes[..from]),
+    }))
+}
+
+// Rounds to the nearest level, values beyond 0-1 are clamped rather than wrapped
+fn to_byte(val: f32) -> u8 {
+    (val.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+// Cleared by --no-atomic for filesystems where renaming over a file is a problem
+static ATOMIC_WRITES: AtomicBool = AtomicBool::new(true);
+
+// Set by --linear, the image is encoded back to sRGB when it is saved
+static LINEAR: AtomicBool = AtomicBool::new(false);
+
+// Set by pipe mode, where the standard output carries the frames
+static REPORTS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+// Set by --dither-output
+static DITHER_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+// Set by --gray-mode for the gray steps that follow
+static GRAY_MODE: Mutex<GrayMode> = Mutex::new(GrayMode::Rec709);
+
+// Set by --scale-filter for the scale steps that follow
+static SCALE_FILTER: Mutex<scale::ScaleFilter> = Mutex::new(scale::ScaleFilter::Nearest);
+
+// Set by --border for the convolutions that follow
+static BORDER: Mutex<Border> = Mutex::new(Border::Constant(0.0));
+
+// Steps changing the settings above for the steps that follow
+const SETTINGS: &[&str] = &[
+    "no-atomic",
+    "linear",
+    "dither-output",
+    "gray-mode",
+    "scale-filter",
+    "border",
+    "threads",
+];
+
+// Back to the defaults of the settings the steps can change
+fn reset_settings() {
+    ATOMIC_WRITES.store(true, Ordering::Relaxed);
+    LINEAR.store(false, Ordering::Relaxed);
+    DITHER_OUTPUT.store(false, Ordering::Relaxed);
+    *GRAY_MODE.lock().unwrap() = GrayMode::Rec709;
+    *SCALE_FILTER.lock().unwrap() = scale::ScaleFilter::Nearest;
+    *BORDER.lock().unwrap() = Border::Constant(0.0);
+    parallel::set_threads(0);
+}
+
+// Output of the steps printing a report, such as stats
+fn report(text: &str) {
+    if REPORTS_TO_STDERR.load(Ordering::Relaxed) {
+        eprint!("{}", text);
+    } else {
+        print!("{}", text);
+    }
+}
+
+fn border() -> Border {
+    *BORDER.lock().unwrap()
+}
+
+// Bytes of the processed image itself, as opposed to exports like histograms,
+// encoded back to sRGB after --linear and dithered after --dither-output
+fn encode_output(image: &PpmFile, seed: u64) -> PackedImage {
+    let mut encoded;
+    let image = if LINEAR.load(Ordering::Relaxed) {
+        encoded = image.clone();
+        color::to_srgb(&mut encoded);
+        &encoded
+    } else {
+        image
+    };
+    if DITHER_OUTPUT.load(Ordering::Relaxed) {
+        PackedImage::pack_dithered(image, seed)
+    } else {
+        PackedImage::pack(image)
+    }
+}
+
+fn save_output(image: &PpmFile, name: &str) -> std::io::Result<()> {
+    save_packed(&encode_output(image, 0), name)
 }
 
 fn save_ppm(image: &PpmFile, name: &str) -> std::io::Result<()> {
+    save_packed(&PackedImage::pack(image), name)
+}
+
+// Writes to a temporary file next to the destination and renames it into place
+// once complete, so a crash never leaves a truncated image behind
+// names ending with .pam keep the alpha channel, in PPM it is dropped
+fn save_packed(image: &PackedImage, name: &str) -> std::io::Result<()> {
+    if name.ends_with(".pam") {
+        save_atomic(name, |path| pam::write_pam(image, path))
+    } else {
+        save_atomic(name, |path| write_ppm(image, path))
+    }
+}
+
+// Runs the writer on a temporary path that is renamed to the name once it
+// succeeds, or on the name itself with --no-atomic
+fn save_atomic<F>(name: &str, write: F) -> std::io::Result<()>
+where
+    F: FnOnce(&str) -> std::io::Result<()>,
+{
+    if !ATOMIC_WRITES.load(Ordering::Relaxed) {
+        return write(name);
+    }
+
+    let path = Path::new(name);
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other(format!("Not a file name: {}", name)))?;
+    let temp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    let result = write(&temp.to_string_lossy()).and_then(|_| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_ppm(image: &PackedImage, name: &str) -> std::io::Result<()> {
     let mut file = File::create(name)?;
-    file.write_all(
-        format!("P6\n{}\n{}\n{}\n", image.width, image.height, image.max_val).as_bytes(),
-    )?;
-
-    let mut bytes: Vec<u8> = Vec::new();
-    bytes.resize(image.pixels.len() * 3, 0u8);
-    for (i, pixel) in image.pixels.iter().enumerate() {
-        bytes[i * 3] = (pixel.r * 255.0) as u8;
-        bytes[i * 3 + 1] = (pixel.g * 255.0) as u8;
-        bytes[i * 3 + 2] = (pixel.b * 255.0) as u8;
-    }
-    file.write_all(&bytes)?;
-    Ok(())
+    file.write_all(ppm_header(image.width, image.height, &image.comments).as_bytes())?;
+    file.write_all(image.pixels.as_flattened())?;
+    file.sync_all()
 }
 
-fn apply_grayscale(image: &mut PpmFile) {
-    for pixel in &mut image.pixels {
-        pixel.r = pixel.r * 0.216 + pixel.g * 0.7125 + pixel.b * 0.0722;
-        pixel.g = pixel.r;
-        pixel.b = pixel.r;
+fn ppm_header(width: usize, height: usize, comments: &[String]) -> String {
+    let mut header = "P6\n".to_string();
+    for comment in comments {
+        header.push_str(&format!("# {}\n", comment));
     }
+    header.push_str(&format!("{}\n{}\n255\n", width, height));
+    header
+}
+
+// Colors are given in hex as `#rrggbb` (the `#` is optional)
+fn parse_color(color: &str) -> Result<Pixel, String> {
+    let hex = color.trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("Expected a color as #rrggbb but {} found!", color));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map(|val| val as f32 / 255.0)
+            .map_err(|error| format!("Bad color {}: {}", color, error))
+    };
+    Ok(Pixel {
+        r: channel(0)?,
+        g: channel(2)?,
+        b: channel(4)?,
+    })
+}
+
+// Rec. 709 relative luminance
+fn luminance(pixel: &Pixel) -> f32 {
+    pixel.r * 0.2126 + pixel.g * 0.7152 + pixel.b * 0.0722
+}
+
+// Weights of the channels in the gray value
+#[derive(Clone, Copy)]
+enum GrayMode {
+    Rec601,
+    Rec709,
+    Average,
+}
+
+impl GrayMode {
+    fn parse(name: &str) -> Result<GrayMode, String> {
+        match name {
+            "rec601" => Ok(GrayMode::Rec601),
+            "rec709" => Ok(GrayMode::Rec709),
+            "average" => Ok(GrayMode::Average),
+            _ => Err(format!("Unknown gray mode: {}", name)),
+        }
+    }
+
+    fn weights(self) -> [f32; 3] {
+        match self {
+            GrayMode::Rec601 => [0.299, 0.587, 0.114],
+            GrayMode::Rec709 => [0.2126, 0.7152, 0.0722],
+            GrayMode::Average => [1.0 / 3.0; 3],
+        }
+    }
+}
+
+fn apply_grayscale(image: &mut PpmFile, mode: GrayMode) {
+    let [wr, wg, wb] = mode.weights();
+    parallel::for_each_row(&mut image.pixels, image.width, |_, row| {
+        for pixel in row {
+            let val = pixel.r * wr + pixel.g * wg + pixel.b * wb;
+            pixel.r = val;
+            pixel.g = val;
+            pixel.b = val;
+        }
+    });
 }
 
 // 3*3 kernel
 fn apply_gaussian_blur(image: &mut PpmFile) {
-    let pixels = image.pixels.clone();
-    for y in 0..image.height {
-        for x in 0..image.width {
-            let mut val: f32 = 0.0;
-            // previous row
-            if y >= 1 {
-                if x >= 1 {
-                    val += pixels[(y - 1) * image.width + x - 1].r / 16.0;
-                }
-                val += pixels[(y - 1) * image.width + x].r / 8.0;
-                if x + 1 < image.width {
-                    val += pixels[(y - 1) * image.width + x + 1].r / 16.0;
-                }
-            }
-            // current row
-            if x >= 1 {
-                val -= pixels[y * image.width + x - 1].r / 8.0;
+    let kernel = Kernel::new(3, 3, vec![1.0, 2.0, 1.0, 2.0, 4.0, 2.0, 1.0, 2.0, 1.0])
+        .unwrap()
+        .normalized();
+    convolve(image, &kernel, border());
+}
+
+// Horizontal and vertical sobel responses
+fn sobel_gradients(image: &GrayImage) -> (Vec<f32>, Vec<f32>) {
+    let kernelx = Kernel::new(3, 3, vec![-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0]).unwrap();
+    let kernely = Kernel::new(3, 3, vec![1.0, 2.0, 1.0, 0.0, 0.0, 0.0, -1.0, -2.0, -1.0]).unwrap();
+    (
+        convolve_channel(&image.values, image.width, image.height, &kernelx, border()),
+        convolve_channel(&image.values, image.width, image.height, &kernely, border()),
+    )
+}
+
+// Gradient magnitude of the luminance, the image is left untouched
+fn sobel_magnitude(image: &PpmFile) -> GrayImage {
+    let (gradx, grady) = sobel_gradients(&GrayImage::from_luminance(image));
+    GrayImage {
+        width: image.width,
+        height: image.height,
+        values: gradx
+            .iter()
+            .zip(&grady)
+            .map(|(gx, gy)| f32::sqrt(gx * gx + gy * gy))
+            .collect(),
+    }
+}
+
+enum SobelOutput {
+    // magnitudes up to the value are scaled to the full range, higher ones saturate
+    Clamp(f32),
+    // scaled by the strongest magnitude in the image
+    Normalized,
+    // white where the magnitude reaches the threshold, black elsewhere
+    Binary(f32),
+}
+
+fn apply_sobel(image: &mut PpmFile, output: SobelOutput) {
+    let mut edges = sobel_magnitude(image);
+    let max = edges.values.iter().cloned().fold(0.0, f32::max);
+    for val in &mut edges.values {
+        *val = match output {
+            SobelOutput::Clamp(limit) => f32::min(*val / limit, 1.0),
+            SobelOutput::Normalized if max > 0.0 => *val / max,
+            SobelOutput::Normalized => 0.0,
+            SobelOutput::Binary(threshold) if *val >= threshold => 1.0,
+            SobelOutput::Binary(_) => 0.0,
+        };
+    }
+    let mut edges = edges.to_ppm();
+    edges.max_val = image.max_val;
+    edges.comments = mem::take(&mut image.comments);
+    *image = edges;
+}
+
+// blur, gradient, non-maximum suppression, double threshold and hysteresis
+fn apply_canny(image: &mut PpmFile, low: f32, high: f32) {
+    apply_gaussian_blur(image);
+    let (gradx, grady) = sobel_gradients(&GrayImage::from_luminance(image));
+    let width = image.width;
+    let height = image.height;
+    let magnitude: Vec<f32> = gradx
+        .iter()
+        .zip(&grady)
+        .map(|(gx, gy)| f32::sqrt(gx * gx + gy * gy))
+        .collect();
+
+    // keep only the local maxima along the gradient direction
+    let mut thin = vec![0.0f32; magnitude.len()];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let i = y * width + x;
+            let mut angle = f32::atan2(grady[i], gradx[i]).to_degrees();
+            if angle < 0.0 {
+                angle += 180.0;
             }
-            val += pixels[y * image.width + x].r / 4.0;
-            if x + 1 < image.width {
-                val += pixels[y * image.width + x + 1].r / 8.0;
+            // valy grows upwards, so the 45 degree neighbours are top-right/bottom-left
+            let (a, b) = if !(22.5..157.5).contains(&angle) {
+                (i - 1, i + 1)
+            } else if angle < 67.5 {
+                (i - width + 1, i + width - 1)
+            } else if angle < 112.5 {
+                (i - width, i + width)
+            } else {
+                (i - width - 1, i + width + 1)
+            };
+            if magnitude[i] >= magnitude[a] && magnitude[i] >= magnitude[b] {
+                thin[i] = magnitude[i];
             }
-            // next row
-            if y + 1 < image.height {
-                if x >= 1 {
-                    val += pixels[(y + 1) * image.width + x - 1].r / 16.0;
-                }
-                val += pixels[(y + 1) * image.width + x].r / 8.0;
-                if x + 1 < image.width {
-                    val += pixels[(y + 1) * image.width + x + 1].r / 16.0;
+        }
+    }
+
+    // strong edges seed a flood fill through the connected weak ones
+    let mut edges = vec![false; thin.len()];
+    let mut stack: Vec<usize> = Vec::new();
+    for (i, val) in thin.iter().enumerate() {
+        if *val >= high {
+            edges[i] = true;
+            stack.push(i);
+        }
+    }
+    while let Some(i) = stack.pop() {
+        let x = i % width;
+        let y = i / width;
+        for ny in y.saturating_sub(1)..usize::min(y + 2, height) {
+            for nx in x.saturating_sub(1)..usize::min(x + 2, width) {
+                let n = ny * width + nx;
+                if !edges[n] && thin[n] >= low {
+                    edges[n] = true;
+                    stack.push(n);
                 }
             }
-            image.pixels[y * image.width + x].r = val;
-            image.pixels[y * image.width + x].g = val;
-            image.pixels[y * image.width + x].b = val;
         }
     }
+
+    for (pixel, edge) in image.pixels.iter_mut().zip(edges) {
+        let val = if edge { 1.0 } else { 0.0 };
+        pixel.r = val;
+        pixel.g = val;
+        pixel.b = val;
+    }
 }
 
-fn apply_sobel(image: &mut PpmFile) {
-    let pixels = image.pixels.clone();
-    for y in 0..image.height {
-        for x in 0..image.width {
-            let mut valx: f32 = 0.0;
-            let mut valy: f32 = 0.0;
-            // previous row
-            if y >= 1 {
-                if x >= 1 {
-                    valx -= pixels[(y - 1) * image.width + x - 1].r;
-                    valy += pixels[(y - 1) * image.width + x - 1].r;
-                }
-                valy += 2.0 * pixels[(y - 1) * image.width + x].r;
-                if x + 1 < image.width {
-                    valx += pixels[(y - 1) * image.width + x + 1].r;
-                    valy += pixels[(y - 1) * image.width + x + 1].r;
-                }
+// 3*3 kernel, 4-neighbourhood, of the luminance
+fn apply_laplacian(image: &mut PpmFile) {
+    let luminance = GrayImage::from_luminance(image).values;
+    let kernel = Kernel::new(3, 3, vec![0.0, 1.0, 0.0, 1.0, -4.0, 1.0, 0.0, 1.0, 0.0]).unwrap();
+    let vals = convolve_channel(&luminance, image.width, image.height, &kernel, border());
+    for (pixel, val) in image.pixels.iter_mut().zip(vals) {
+        let val = f32::min(val.abs(), 1.0);
+        pixel.r = val;
+        pixel.g = val;
+        pixel.b = val;
+    }
+}
+
+fn apply_laplacian_of_gaussian(image: &mut PpmFile) {
+    apply_gaussian_blur(image);
+    apply_laplacian(image);
+}
+
+// Band pass: the image blurred with the wider sigma is subtracted from the one
+// blurred with the narrower sigma, amplified by the gain around mid gray
+fn apply_difference_of_gaussians(
+    image: &mut PpmFile,
+    sigma1: f32,
+    sigma2: f32,
+    gain: f32,
+) -> Result<(), String> {
+    let mut narrow = image.clone();
+    convolve(
+        &mut narrow,
+        &Kernel::gaussian_sigma(f32::min(sigma1, sigma2))?,
+        border(),
+    );
+    let mut wide = image.clone();
+    convolve(
+        &mut wide,
+        &Kernel::gaussian_sigma(f32::max(sigma1, sigma2))?,
+        border(),
+    );
+    for ((pixel, n), w) in image
+        .pixels
+        .iter_mut()
+        .zip(&narrow.pixels)
+        .zip(&wide.pixels)
+    {
+        pixel.r = (0.5 + gain * (n.r - w.r)).clamp(0.0, 1.0);
+        pixel.g = (0.5 + gain * (n.g - w.g)).clamp(0.0, 1.0);
+        pixel.b = (0.5 + gain * (n.b - w.b)).clamp(0.0, 1.0);
+    }
+    Ok(())
+}
+
+// Original minus its blurred copy around the offset, the blur always
+// replicates the edge pixels so the borders don't light up
+fn apply_high_pass(image: &mut PpmFile, sigma: f32, offset: f32) -> Result<(), String> {
+    let mut blurred = image.clone();
+    convolve(
+        &mut blurred,
+        &Kernel::gaussian_sigma(sigma)?,
+        Border::Replicate,
+    );
+    for (pixel, b) in image.pixels.iter_mut().zip(&blurred.pixels) {
+        pixel.r = (pixel.r - b.r + offset).clamp(0.0, 1.0);
+        pixel.g = (pixel.g - b.g + offset).clamp(0.0, 1.0);
+        pixel.b = (pixel.b - b.b + offset).clamp(0.0, 1.0);
+    }
+    Ok(())
+}
+
+// Filter parameters are given as `name=a,b,c`
+fn parse_params(filter: &str, value: Option<&str>, defaults: &[f32]) -> Vec<f32> {
+    let mut params = defaults.to_vec();
+    if let Some(value) = value {
+        for (i, param) in value.split(',').enumerate() {
+            if i >= params.len() {
+                panic!("Too many parameters given to {}: {}", filter, value);
             }
-            // current row
-            if x >= 1 {
-                valx -= 2.0 * pixels[y * image.width + x - 1].r;
+            params[i] = param
+                .trim()
+                .parse::<f32>()
+                .unwrap_or_else(|error| panic!("Bad parameter for {}: {}", filter, error));
+        }
+    }
+    params
+}
+
+// Values can also be given as the next argument, e.g. `--kernel "1,1,1;1,1,1;1,1,1"`
+fn required_value<'a>(
+    filter: &str,
+    value: Option<&'a str>,
+    rest: &mut std::slice::Iter<'a, String>,
+) -> &'a str {
+    value
+        .or_else(|| rest.next().map(|next| next.as_str()))
+        .unwrap_or_else(|| panic!("{} expects a value!", filter))
+}
+
+// Applies the steps in order until the arguments run out (false) or a
+// branch is closed by `]` (true)
+fn run_pipeline(
+    ppm: &mut PpmFile,
+    ops: &mut std::slice::Iter<String>,
+) -> Result<bool, Box<dyn Error>> {
+    while let Some(arg) = ops.next() {
+        if apply_step(ppm, arg, ops)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Steps moving pixels around, the alpha channel is moved along with them.
+// Areas they uncover become transparent.
+const GEOMETRIC: &[&str] = &[
+    "scale",
+    "letterbox",
+    "rotate",
+    "affine",
+    "perspective",
+    "flip-h",
+    "flip-v",
+];
+
+// Steps writing files or printing reports on top of what they do to the image
+const SIDE_EFFECTS: &[&str] = &[
+    "tee",
+    "timing",
+    "histogram",
+    "stats",
+    "term",
+    "hist3d",
+    "palette",
+    "waveform",
+    "vectorscope",
+];
+
+// Applies a single step, values it needs may be taken from the following arguments
+fn apply_step<'a>(
+    ppm: &mut PpmFile,
+    arg: &'a str,
+    ops: &mut std::slice::Iter<'a, String>,
+) -> Result<bool, Box<dyn Error>> {
+    let alpha = match &ppm.alpha {
+        Some(alpha) => alpha.clone(),
+        None => return apply_filter(ppm, arg, ops),
+    };
+    let name = arg.trim_start_matches("--").split('=').next().unwrap_or("");
+    if GEOMETRIC.contains(&name) {
+        // the same step on the alpha as a gray image, reading the same values
+        let mut mask = GrayImage {
+            width: ppm.width,
+            height: ppm.height,
+            values: alpha,
+        }
+        .to_ppm();
+        let mut mask_ops = ops.clone();
+        // the pad of letterbox is transparent whatever its color
+        let mask_arg = match name {
+            "letterbox" => {
+                let value = match arg.split_once('=') {
+                    Some((_, value)) => value,
+                    None => mask_ops.next().map(|value| value.as_str()).unwrap_or(""),
+                };
+                let size: Vec<&str> = value.splitn(3, ',').take(2).collect();
+                format!("letterbox={}", size.join(","))
             }
+            _ => arg.to_string(),
+        };
+        ppm.alpha = None;
+        let closed = apply_filter(ppm, arg, ops)?;
+        apply_filter(&mut mask, &mask_arg, &mut mask_ops)?;
+        // scale filters may overshoot
+        ppm.alpha = Some(
+            mask.pixels
+                .iter()
+                .map(|pixel| pixel.r.clamp(0.0, 1.0))
+                .collect(),
+        );
+        return Ok(closed);
+    }
+
+    // every other step keeps the alpha as it is, even when it builds a new image
+    let (width, height) = (ppm.width, ppm.height);
+    let closed = apply_filter(ppm, arg, ops)?;
+    if ppm.alpha.is_none() {
+        if ppm.width == width && ppm.height == height {
+            ppm.alpha = Some(alpha);
+        } else {
+            eprintln!("{} changed the size of the image, dropping its alpha", name);
+        }
+    }
+    Ok(closed)
+}
 
-            if x + 1 < image.width {
-                valx += 2.0 * pixels[y * image.width + x + 1].r;
+fn apply_filter<'a>(
+    ppm: &mut PpmFile,
+    arg: &'a str,
+    ops: &mut std::slice::Iter<'a, String>,
+) -> Result<bool, Box<dyn Error>> {
+    let arg = arg.trim_start_matches("--");
+    let (name, value) = match arg.find('=') {
+        Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+        None => (arg, None),
+    };
+    match name {
+        "[" => {
+            // the steps up to the matching ] work on a copy of the image
+            let mut branch = ppm.clone();
+            if !run_pipeline(&mut branch, ops)? {
+                panic!("A branch is missing its closing ]!");
             }
-            // next row
-            if y + 1 < image.height {
-                if x >= 1 {
-                    valx -= pixels[(y + 1) * image.width + x - 1].r;
-                    valy -= pixels[(y + 1) * image.width + x - 1].r;
+        }
+        "]" => return Ok(true),
+        "tee" => save_output(ppm, required_value(name, value, ops))?,
+        "timing" => {
+            // timing=<file> <step>, the step's own value may follow as usual
+            let out = required_value(name, value, ops);
+            let step = ops
+                .next()
+                .unwrap_or_else(|| panic!("timing expects a step to measure!"));
+            timing::check_step(step).unwrap_or_else(|error| panic!("{}", error));
+            let rest = ops.clone();
+            timing::export_heatmap(ppm, out, |tile| apply_step(tile, step, &mut rest.clone()))?;
+            apply_step(ppm, step, ops)?;
+        }
+        "no-atomic" => ATOMIC_WRITES.store(false, Ordering::Relaxed),
+        "comment" => ppm.add_comment(required_value(name, value, ops)),
+        "no-comments" => ppm.comments.clear(),
+        "dither-output" => DITHER_OUTPUT.store(true, Ordering::Relaxed),
+        "linear" => {
+            color::to_linear(ppm);
+            LINEAR.store(true, Ordering::Relaxed);
+        }
+        "gray" => apply_grayscale(ppm, *GRAY_MODE.lock().unwrap()),
+        "gray-mode" => {
+            *GRAY_MODE.lock().unwrap() = GrayMode::parse(required_value(name, value, ops))
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "gauss" => apply_gaussian_blur(ppm),
+        "sobel" => {
+            // sobel[=<clamp|normalized|binary>[,<value>]]
+            let value = value.unwrap_or("clamp");
+            let (mode, params) = match value.split_once(',') {
+                Some((mode, params)) => (mode, Some(params)),
+                None => (value, None),
+            };
+            let output = match mode {
+                "clamp" => {
+                    let limit = parse_params(name, params, &[1.0])[0];
+                    if limit <= 0.0 {
+                        panic!(
+                            "The sobel clamp value should be positive but {} given!",
+                            limit
+                        );
+                    }
+                    SobelOutput::Clamp(limit)
+                }
+                "normalized" => SobelOutput::Normalized,
+                "binary" => SobelOutput::Binary(parse_params(name, params, &[0.5])[0]),
+                _ => panic!("Unknown sobel output: {}", mode),
+            };
+            apply_sobel(ppm, output);
+        }
+        "laplace" => apply_laplacian(ppm),
+        "log" => apply_laplacian_of_gaussian(ppm),
+        "dog" => {
+            let params = parse_params(name, value, &[1.0, 1.6, 4.0]);
+            apply_difference_of_gaussians(ppm, params[0], params[1], params[2])
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "highpass" => {
+            let params = parse_params(name, value, &[3.0, 0.5]);
+            apply_high_pass(ppm, params[0], params[1]).unwrap_or_else(|error| panic!("{}", error));
+        }
+        "canny" => {
+            let params = parse_params(name, value, &[0.1, 0.3]);
+            apply_canny(ppm, params[0], params[1]);
+        }
+        "hue-shift" => {
+            let params = parse_params(name, Some(required_value(name, value, ops)), &[0.0]);
+            color::apply_hsv(ppm, params[0], 1.0, 1.0);
+        }
+        "saturation" => {
+            let params = parse_params(name, Some(required_value(name, value, ops)), &[1.0]);
+            color::apply_hsv(ppm, 0.0, params[0], 1.0);
+        }
+        "value" => {
+            let params = parse_params(name, Some(required_value(name, value, ops)), &[1.0]);
+            color::apply_hsv(ppm, 0.0, 1.0, params[0]);
+        }
+        "to-space" | "from-space" => {
+            let space = colorspace::ColorSpace::parse(required_value(name, value, ops))
+                .unwrap_or_else(|error| panic!("{}", error));
+            if name == "to-space" {
+                colorspace::to_space(ppm, space);
+            } else {
+                colorspace::from_space(ppm, space);
+            }
+        }
+        "white-balance" => {
+            let method = match value {
+                None | Some("gray-world") => color::WhiteBalance::GrayWorld,
+                Some("white-patch") => color::WhiteBalance::WhitePatch,
+                Some(other) => panic!("Unknown white balance method: {}", other),
+            };
+            color::apply_white_balance(ppm, method);
+        }
+        "temperature" => {
+            let params = parse_params(name, Some(required_value(name, value, ops)), &[6500.0]);
+            color::apply_temperature(ppm, params[0], 0.0);
+        }
+        "tint" => {
+            let params = parse_params(name, Some(required_value(name, value, ops)), &[0.0]);
+            color::apply_temperature(ppm, 6500.0, params[0]);
+        }
+        "highlights" => {
+            let params = parse_params(name, value, &[3.0]);
+            color::reconstruct_highlights(ppm, params[0] as usize);
+        }
+        "threshold" => {
+            let cutoff = match value {
+                None | Some("otsu") => threshold::otsu_threshold(ppm),
+                Some(_) => parse_params(name, value, &[0.5])[0],
+            };
+            threshold::apply_threshold(ppm, cutoff);
+        }
+        "adaptive-mean" | "adaptive-gauss" => {
+            let params = parse_params(name, value, &[15.0, 0.02]);
+            let method = if name == "adaptive-mean" {
+                threshold::LocalMean::Mean
+            } else {
+                threshold::LocalMean::Gaussian
+            };
+            threshold::apply_adaptive_threshold(ppm, method, params[0] as usize, params[1])
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "colormap" => {
+            let colormap = colormap::Colormap::load(required_value(name, value, ops))
+                .unwrap_or_else(|error| panic!("{}", error));
+            colormap::apply_colormap(ppm, &colormap);
+        }
+        "contours" => {
+            // contours=<interval>[,<color>]
+            let (interval, color) = match value.map(|v| v.split_once(',')) {
+                Some(Some((interval, color))) => (Some(interval), color),
+                Some(None) => (value, "#ff0000"),
+                None => (None, "#ff0000"),
+            };
+            let interval = parse_params(name, interval, &[0.1])[0];
+            let color = parse_color(color).unwrap_or_else(|error| panic!("{}", error));
+            contour::draw_contours(ppm, interval, &color)
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "noise" => {
+            // noise=<gaussian|salt>[,<amount>[,<seed>]]
+            let value = value.unwrap_or("gaussian");
+            let (method, params) = match value.split_once(',') {
+                Some((method, params)) => (method, Some(params)),
+                None => (value, None),
+            };
+            match method {
+                "gaussian" => {
+                    let params = parse_params(name, params, &[0.05, 0.0]);
+                    noise::apply_gaussian_noise(ppm, params[0], params[1] as u64);
                 }
-                valy -= 2.0 * pixels[(y + 1) * image.width + x].r;
-                if x + 1 < image.width {
-                    valx += pixels[(y + 1) * image.width + x + 1].r;
-                    valy -= pixels[(y + 1) * image.width + x + 1].r;
+                "salt" => {
+                    let params = parse_params(name, params, &[0.02, 0.0]);
+                    noise::apply_salt_and_pepper(ppm, params[0], params[1] as u64);
                 }
+                _ => panic!("Unknown noise type: {}", method),
             }
-
-            let grad = f32::sqrt(valx * valx + valy * valy);
-            if grad > 1.0 {
-                image.pixels[y * image.width + x].r = 1.0;
-                image.pixels[y * image.width + x].g = 1.0;
-                image.pixels[y * image.width + x].b = 1.0;
+        }
+        "nlmeans" => {
+            let params = parse_params(name, value, &[0.1, 1.0, 5.0]);
+            denoise::apply_nl_means(ppm, params[0], params[1] as usize, params[2] as usize);
+        }
+        "bayer" => {
+            let params = parse_params(name, value, &[4.0, 2.0]);
+            dither::apply_bayer(ppm, params[0] as usize, params[1] as usize)
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "halftone" => {
+            let params = parse_params(name, value, &[8.0, 45.0]);
+            dither::apply_halftone(ppm, params[0], params[1])
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "quantize" => {
+            let params = parse_params(name, value, &[16.0, 0.0]);
+            let palette = quantize::median_cut(ppm, params[0] as usize);
+            quantize::apply_palette(ppm, &palette, params[1] != 0.0);
+        }
+        "oil" => {
+            let params = parse_params(name, value, &[3.0, 20.0]);
+            stylize::apply_oil_painting(ppm, params[0] as usize, params[1] as usize);
+        }
+        "kuwahara" => {
+            let radius = parse_params(name, value, &[2.0])[0] as usize;
+            stylize::apply_kuwahara(ppm, radius);
+        }
+        "motion" => {
+            let params = parse_params(name, value, &[9.0, 0.0]);
+            let kernel =
+                Kernel::motion(params[0], params[1]).unwrap_or_else(|error| panic!("{}", error));
+            convolve(ppm, &kernel, border());
+        }
+        "erode" | "dilate" => {
+            let element = morphology::StructuringElement::parse(value.unwrap_or("square"))
+                .unwrap_or_else(|error| panic!("{}", error));
+            if name == "erode" {
+                morphology::apply_erosion(ppm, &element);
+            } else {
+                morphology::apply_dilation(ppm, &element);
+            }
+        }
+        "channel" => {
+            channels::extract_channel(ppm, required_value(name, value, ops))
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "swap" => {
+            channels::swap_channels(ppm, required_value(name, value, ops))
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "chroma-key" => {
+            // chroma-key=<key color>[,<tolerance>[,<background color>]]
+            let mut parts = value.unwrap_or("#00ff00").splitn(3, ',');
+            let key = parse_color(parts.next().unwrap_or("#00ff00"))
+                .unwrap_or_else(|error| panic!("{}", error));
+            let tolerance = parse_params(name, parts.next(), &[0.15])[0];
+            let background = parse_color(parts.next().unwrap_or("#000000"))
+                .unwrap_or_else(|error| panic!("{}", error));
+            color::apply_chroma_key(ppm, &key, tolerance, &background);
+        }
+        "tonemap" => {
+            // tonemap=<reinhard|filmic>[,<exposure>]
+            let value = value.unwrap_or("reinhard");
+            let (method, params) = match value.split_once(',') {
+                Some((method, params)) => (method, Some(params)),
+                None => (value, None),
+            };
+            let method = match method {
+                "reinhard" => tonemap::ToneMap::Reinhard,
+                "filmic" => tonemap::ToneMap::Filmic,
+                _ => panic!("Unknown tone mapping: {}", method),
+            };
+            let exposure = parse_params(name, params, &[1.0])[0];
+            tonemap::apply_tone_map(ppm, method, exposure);
+        }
+        "cartoon" => {
+            let params = parse_params(name, value, &[3.0, 6.0, 0.4]);
+            stylize::apply_cartoon(ppm, params[0] as usize, params[1] as usize, params[2]);
+        }
+        "fx" => {
+            fx::apply_fx(ppm, required_value(name, value, ops))
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "border" => {
+            *BORDER.lock().unwrap() = Border::parse(required_value(name, value, ops))
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "threads" => {
+            let count = parse_params(name, Some(required_value(name, value, ops)), &[0.0])[0];
+            parallel::set_threads(count as usize);
+        }
+        "scale-filter" => {
+            *SCALE_FILTER.lock().unwrap() =
+                scale::ScaleFilter::parse(required_value(name, value, ops))
+                    .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "scale" => {
+            let params = parse_params(name, Some(required_value(name, value, ops)), &[0.0, 0.0]);
+            let (width, height) = scale::target_size(ppm, params[0] as usize, params[1] as usize)
+                .unwrap_or_else(|error| panic!("{}", error));
+            let filter = *SCALE_FILTER.lock().unwrap();
+            scale::scale(ppm, width, height, filter)?;
+        }
+        "letterbox" => {
+            // letterbox=<width>,<height>[,<fill color>]
+            let value = required_value(name, value, ops);
+            let (size, fill) = match value.splitn(3, ',').nth(2) {
+                Some(fill) => (&value[..value.len() - fill.len() - 1], fill),
+                None => (value, "#000000"),
+            };
+            let params = parse_params(name, Some(size), &[0.0, 0.0]);
+            let (width, height) = (params[0] as usize, params[1] as usize);
+            if width == 0 || height == 0 || ppm.width == 0 || ppm.height == 0 {
+                panic!("Letterboxing needs a canvas and an image that aren't empty!");
+            }
+            let fill = parse_color(fill).unwrap_or_else(|error| panic!("{}", error));
+            // the largest size with the aspect ratio of the image that fits the canvas
+            let ratio = f32::min(
+                width as f32 / ppm.width as f32,
+                height as f32 / ppm.height as f32,
+            );
+            let fit_w = usize::clamp((ppm.width as f32 * ratio).round() as usize, 1, width);
+            let fit_h = usize::clamp((ppm.height as f32 * ratio).round() as usize, 1, height);
+            let filter = *SCALE_FILTER.lock().unwrap();
+            scale::scale(ppm, fit_w, fit_h, filter)?;
+            compose::pad(ppm, width, height, &fill)?;
+        }
+        "rotate" => {
+            let degrees = parse_params(name, Some(required_value(name, value, ops)), &[90.0])[0];
+            geometry::rotate(ppm, degrees as i32).unwrap_or_else(|error| panic!("{}", error));
+        }
+        "affine" => {
+            let params = parse_params(
+                name,
+                Some(required_value(name, value, ops)),
+                &[1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            );
+            geometry::apply_affine(ppm, &params).unwrap_or_else(|error| panic!("{}", error));
+        }
+        "perspective" => {
+            let params = parse_params(name, Some(required_value(name, value, ops)), &[0.0; 8]);
+            geometry::apply_perspective(ppm, &params).unwrap_or_else(|error| panic!("{}", error));
+        }
+        "flip-h" => geometry::flip_horizontal(ppm),
+        "flip-v" => geometry::flip_vertical(ppm),
+        "compose" => {
+            // compose=<overlay>[,<x>[,<y>[,<opacity>[,<blend mode>]]]]
+            let mut parts = required_value(name, value, ops).splitn(5, ',');
+            let overlay = parse_ppm(parts.next().unwrap_or(""))?;
+            let params: Vec<&str> = parts.collect();
+            let blend = match params.get(3) {
+                Some(mode) => {
+                    compose::Blend::parse(mode).unwrap_or_else(|error| panic!("{}", error))
+                }
+                None => compose::Blend::Normal,
+            };
+            let numbers = params[..usize::min(params.len(), 3)].join(",");
+            let numbers = parse_params(
+                name,
+                Some(numbers.as_str()).filter(|n| !n.is_empty()),
+                &[0.0, 0.0, 1.0],
+            );
+            compose::compose(
+                ppm,
+                &overlay,
+                numbers[0] as i64,
+                numbers[1] as i64,
+                numbers[2],
+                blend,
+            );
+        }
+        "watermark" => {
+            // watermark=<image>[,<corner>[,<opacity>[,<margin>]]]
+            let mut parts = required_value(name, value, ops).splitn(3, ',');
+            let mark = parse_ppm(parts.next().unwrap_or(""))?;
+            let corner = parts.next().unwrap_or("br");
+            let params = parse_params(name, parts.next(), &[0.5, 10.0]);
+            let margin = params[1] as i64;
+            let right = ppm.width as i64 - mark.width as i64 - margin;
+            let bottom = ppm.height as i64 - mark.height as i64 - margin;
+            let (x, y) = match corner {
+                "tl" => (margin, margin),
+                "tr" => (right, margin),
+                "bl" => (margin, bottom),
+                "br" => (right, bottom),
+                _ => panic!(
+                    "Expected a corner as tl, tr, bl or br but {} given!",
+                    corner
+                ),
+            };
+            compose::compose(ppm, &mark, x, y, params[0], compose::Blend::Normal);
+        }
+        "text" => {
+            // text=<x>,<y>,<scale>,<color>,<text>, the text may hold commas
+            let value = required_value(name, value, ops);
+            let parts: Vec<&str> = value.splitn(5, ',').collect();
+            if parts.len() < 5 {
+                panic!(
+                    "Expected text as <x>,<y>,<scale>,<color>,<text> but {} given!",
+                    value
+                );
+            }
+            let params = parse_params(name, Some(&parts[..3].join(",")), &[0.0, 0.0, 1.0]);
+            let color = parse_color(parts[3]).unwrap_or_else(|error| panic!("{}", error));
+            // `\n` starts a new line, a real newline is awkward to pass
+            let text = parts[4].replace("\\n", "\n");
+            draw::draw_text(
+                ppm,
+                params[0] as i64,
+                params[1] as i64,
+                params[2] as usize,
+                &color,
+                &text,
+            );
+        }
+        "sepia" => {
+            let params = parse_params(name, value, &[1.0]);
+            color::apply_sepia(ppm, params[0]);
+        }
+        "invert" => color::apply_invert(ppm),
+        "solarize" => {
+            let params = parse_params(name, value, &[0.5]);
+            color::apply_solarize(ppm, params[0]);
+        }
+        "mosaic" => {
+            // mosaic=<cell>[,<tile directory>]
+            let (cell, tiles) = match value.map(|v| v.split_once(',')) {
+                Some(Some((cell, dir))) => (
+                    Some(cell),
+                    mosaic::load_tiles(dir).unwrap_or_else(|error| panic!("{}", error)),
+                ),
+                _ => (value, Vec::new()),
+            };
+            let cell = parse_params(name, cell, &[16.0])[0] as usize;
+            mosaic::apply_mosaic(ppm, cell, &tiles).unwrap_or_else(|error| panic!("{}", error));
+        }
+        "levels" | "levels-r" | "levels-g" | "levels-b" => {
+            let params = parse_params(name, value, &[0.0, 1.0, 1.0, 0.0, 1.0]);
+            let levels = color::Levels {
+                in_black: params[0],
+                in_white: params[1],
+                gamma: params[2],
+                out_black: params[3],
+                out_white: params[4],
+            };
+            let channels = match name {
+                "levels-r" => [true, false, false],
+                "levels-g" => [false, true, false],
+                "levels-b" => [false, false, true],
+                _ => [true, true, true],
+            };
+            color::apply_levels(ppm, &levels, channels);
+        }
+        "equalize" => histogram::apply_equalization(ppm),
+        "autocontrast" => {
+            // autocontrast[=<low percent>[,<high percent>]], the high one defaults to the low one
+            let params = parse_params(name, value, &[0.5, 0.5]);
+            let high = if value.is_some_and(|value| value.contains(',')) {
+                params[1]
             } else {
-                image.pixels[y * image.width + x].r = grad;
-                image.pixels[y * image.width + x].g = grad;
-                image.pixels[y * image.width + x].b = grad;
+                params[0]
+            };
+            histogram::apply_autocontrast(ppm, params[0], high)
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "clahe" => {
+            let params = parse_params(name, value, &[2.0, 8.0]);
+            histogram::apply_clahe(ppm, params[0], params[1] as usize)
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+        "histogram" => {
+            report(&format!(
+                "{}\n",
+                histogram::render_luminance_histogram(ppm, 64, 8)
+            ));
+        }
+        "stats" => {
+            let stats = stats::compute(ppm);
+            match value {
+                None | Some("text") => report(&format!("{}\n", stats.to_text())),
+                Some("json") => report(&format!("{}\n", stats.to_json())),
+                Some(format) => panic!("Expected stats as text or json but {} given!", format),
             }
         }
+        "term" => {
+            // term[=<columns>], as wide as the terminal by default
+            let columns = match value {
+                Some(_) => parse_params(name, value, &[80.0])[0] as usize,
+                None => env::var("COLUMNS")
+                    .ok()
+                    .and_then(|columns| columns.parse().ok())
+                    .unwrap_or(80),
+            };
+            if LINEAR.load(Ordering::Relaxed) {
+                let mut encoded = ppm.clone();
+                color::to_srgb(&mut encoded);
+                report(&term::render(&encoded, columns));
+            } else {
+                report(&term::render(ppm, columns));
+            }
+        }
+        "hist3d" => {
+            let out = required_value(name, value, ops);
+            histogram::export_histogram3d(ppm, out)?;
+        }
+        "palette" => {
+            // palette=<file>[,<count>]
+            let value = required_value(name, value, ops);
+            let (out, count) = match value.split_once(',') {
+                Some((out, count)) => (out, parse_params(name, Some(count), &[5.0])[0]),
+                None => (value, 5.0),
+            };
+            if count.is_nan() || count < 1.0 {
+                panic!("Palette needs a count of at least 1 but {} given!", count);
+            }
+            palette::export_palette(ppm, out, count as usize)?;
+        }
+        "waveform" => {
+            let out = required_value(name, value, ops);
+            scopes::export_waveform(ppm, out)?;
+        }
+        "vectorscope" => {
+            let out = required_value(name, value, ops);
+            scopes::export_vectorscope(ppm, out)?;
+        }
+        "kernel" => {
+            let kernel = Kernel::parse(required_value(name, value, ops))
+                .unwrap_or_else(|error| panic!("{}", error));
+            convolve(ppm, &kernel, border());
+        }
+        "kernel-file" => {
+            let file = required_value(name, value, ops);
+            let spec = fs::read_to_string(file)
+                .unwrap_or_else(|error| panic!("Could not read kernel file: {}", error));
+            let kernel = Kernel::parse(&spec).unwrap_or_else(|error| panic!("{}", error));
+            convolve(ppm, &kernel, border());
+        }
+        _ => panic!("Unnexpected filter given: {}", arg),
     }
+
+    Ok(false)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -236,28 +1435,439 @@ fn main() -> Result<(), Box<dyn Error>> {
         panic!("Expected a file!");
     }
 
-    let mut ppm = parse_ppm(&args[1]).unwrap_or_else(|error| panic!("{}", error));
-    for arg in &args[2..] {
-        match arg.as_str() {
-            "gray" => apply_grayscale(&mut ppm),
-            "gauss" => apply_gaussian_blur(&mut ppm),
-            "sobel" => apply_sobel(&mut ppm),
-            _ => panic!("Unnexpected filter given: {}", arg),
+    match args[1].as_str() {
+        "pipe" | "--pipe" => {
+            pipe::run(&args[2..])?;
+            return Ok(());
+        }
+        "serve" if args.len() == 3 => {
+            serve::serve(&args[2])?;
+            return Ok(());
+        }
+        "bench" if args.len() >= 3 => {
+            bench::run(&args[2], &args[3..])?;
+            return Ok(());
+        }
+        "gen-fixtures" if args.len() == 3 => {
+            fixtures::generate(&args[2])?;
+            return Ok(());
+        }
+        "generate" if args.len() >= 5 && args[2] == "--pattern" => {
+            // generate --pattern <name> <width>x<height> [--seed <n>] [--frequency <f>]
+            //          [--octaves <n>] [<file>]
+            let (width, height) =
+                generate::parse_size(&args[4]).unwrap_or_else(|error| panic!("{}", error));
+            let mut noise = texture::NoiseParams {
+                seed: 0,
+                frequency: 8.0,
+                octaves: 4,
+            };
+            let mut output = format!("{}.ppm", args[3]);
+            let mut rest = args[5..].iter();
+            while let Some(arg) = rest.next() {
+                let mut number = |default: f32| {
+                    parse_params("generate", rest.next().map(|v| v.as_str()), &[default])[0]
+                };
+                match arg.as_str() {
+                    "--seed" => noise.seed = number(0.0) as u64,
+                    "--frequency" => noise.frequency = number(8.0),
+                    "--octaves" => noise.octaves = number(4.0) as usize,
+                    file => output = file.to_string(),
+                }
+            }
+            let image = generate::pattern(&args[3], width, height, &noise)
+                .unwrap_or_else(|error| panic!("{}", error));
+            save_ppm(&image, &output)?;
+            return Ok(());
+        }
+        "golden" if args.len() >= 3 => {
+            // golden [--update] [--tolerance <n>] <dir>
+            let mut update = false;
+            let mut tolerance = 1.0;
+            let mut rest = args[2..].iter();
+            let dir = loop {
+                match rest.next().map(|arg| arg.as_str()) {
+                    Some("--update") => update = true,
+                    Some("--tolerance") => {
+                        tolerance =
+                            parse_params("golden", rest.next().map(|v| v.as_str()), &[1.0])[0]
+                    }
+                    Some(dir) => break dir,
+                    None => panic!("Expected a directory of golden images!"),
+                }
+            };
+            fixtures::check(dir, update, tolerance as u8)?;
+            return Ok(());
+        }
+        "tiled" if args.len() >= 3 => {
+            // tiled [--rows <n>] <file> <steps...>
+            let (rows, rest) = match args[2].as_str() {
+                "--rows" if args.len() >= 5 => (
+                    parse_params("tiled", Some(&args[3]), &[256.0])[0] as usize,
+                    &args[4..],
+                ),
+                _ => (256, &args[2..]),
+            };
+            let output = format!(
+                "{}_new.ppm",
+                Path::new(&rest[0]).file_stem().unwrap().to_string_lossy()
+            );
+            tiled::run(&rest[0], &output, rows, &rest[1..])?;
+            return Ok(());
+        }
+        "info" if args.len() >= 3 => {
+            // the format of every file, with its size or why it can't be decoded
+            for file in &args[2..] {
+                let bytes = fs::read(file)?;
+                let format = match detect_format(&bytes) {
+                    Some(format) => format.to_string(),
+                    None => "unknown format".to_string(),
+                };
+                match decode_image(&bytes) {
+                    Ok(Loaded::Packed(PackedImage { width, height, .. }))
+                    | Ok(Loaded::Float(PpmFile { width, height, .. })) => {
+                        println!("{}: {}, {}x{}", file, format, width, height)
+                    }
+                    Err(PpmError::UnknownFormat) | Err(PpmError::UnsupportedFormat(_)) => {
+                        println!("{}: {}", file, format)
+                    }
+                    Err(error) => println!("{}: {}, {}", file, format, error),
+                }
+            }
+            return Ok(());
+        }
+        "identify" if args.len() >= 3 => {
+            for file in &args[2..] {
+                // a broken header ends its listing, the other files still get theirs
+                if let Err(error) = identify::identify(file) {
+                    println!("  {}", error);
+                }
+            }
+            return Ok(());
+        }
+        "index" if args.len() == 3 => {
+            similar::index(&args[2]).unwrap_or_else(|error| panic!("{}", error));
+            return Ok(());
+        }
+        "compare" if args.len() == 4 => {
+            let a = parse_ppm(&args[2]).unwrap_or_else(|error| panic!("{}", error));
+            let b = parse_ppm(&args[3]).unwrap_or_else(|error| panic!("{}", error));
+            let psnr = compare::psnr(&a, &b).unwrap_or_else(|error| panic!("{}", error));
+            let ssim = compare::ssim(&a, &b).unwrap_or_else(|error| panic!("{}", error));
+            println!("PSNR: {:.2} dB", psnr);
+            println!("SSIM: {:.4}", ssim);
+            return Ok(());
+        }
+        "diff" if args.len() >= 5 => {
+            // diff [--gain <n>] [--colormap <name>] <a> <b> <output>
+            let mut gain = 1.0;
+            let mut colormap = None;
+            let mut rest = args[2..].iter();
+            let first = loop {
+                match rest.next().map(|arg| arg.as_str()) {
+                    Some("--gain") => {
+                        gain = parse_params("diff", rest.next().map(|v| v.as_str()), &[1.0])[0]
+                    }
+                    Some("--colormap") => {
+                        colormap = Some(
+                            colormap::Colormap::load(rest.next().map_or("", |v| v.as_str()))
+                                .unwrap_or_else(|error| panic!("{}", error)),
+                        )
+                    }
+                    Some(first) => break first,
+                    None => panic!("Expected two images and an output file for the diff!"),
+                }
+            };
+            let files: Vec<&String> = rest.collect();
+            if files.len() != 2 {
+                panic!("Expected two images and an output file for the diff!");
+            }
+            let a = parse_ppm(first).unwrap_or_else(|error| panic!("{}", error));
+            let b = parse_ppm(files[0]).unwrap_or_else(|error| panic!("{}", error));
+            let diff = compare::difference(&a, &b, gain, colormap.as_ref())
+                .unwrap_or_else(|error| panic!("{}", error));
+            save_ppm(&diff, files[1])?;
+            return Ok(());
+        }
+        "combine" if args.len() == 6 => {
+            let channels: Vec<PpmFile> = args[2..5]
+                .iter()
+                .map(|file| parse_ppm(file).unwrap_or_else(|error| panic!("{}", error)))
+                .collect();
+            let rgb = channels::combine(&channels[0], &channels[1], &channels[2])
+                .unwrap_or_else(|error| panic!("{}", error));
+            save_ppm(&rgb, &args[5])?;
+            return Ok(());
+        }
+        "montage" if args.len() >= 4 => {
+            // montage [--columns <n>] [--gap <pixels>] [--background <color>] <output> <images...>
+            let mut columns = 0;
+            let mut gap = 0;
+            let mut background = Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            };
+            let mut rest = args[2..].iter();
+            let output = loop {
+                match rest.next().map(|arg| arg.as_str()) {
+                    Some("--columns") => {
+                        columns = parse_params("montage", rest.next().map(|v| v.as_str()), &[0.0])
+                            [0] as usize
+                    }
+                    Some("--gap") => {
+                        gap = parse_params("montage", rest.next().map(|v| v.as_str()), &[0.0])[0]
+                            as usize
+                    }
+                    Some("--background") => {
+                        background = parse_color(rest.next().map_or("", |v| v.as_str()))
+                            .unwrap_or_else(|error| panic!("{}", error))
+                    }
+                    Some(output) => break output,
+                    None => panic!("Expected an output file for the montage!"),
+                }
+            };
+            let images: Vec<PpmFile> = rest
+                .map(|file| parse_ppm(file).unwrap_or_else(|error| panic!("{}", error)))
+                .collect();
+            if images.is_empty() {
+                panic!("Expected images for the montage!");
+            }
+            // a square-ish grid unless told otherwise
+            if columns == 0 {
+                columns = (images.len() as f32).sqrt().ceil() as usize;
+            }
+            let sheet = compose::montage(&images, columns, gap, &background)?;
+            save_ppm(&sheet, output)?;
+            return Ok(());
+        }
+        "scopes" if args.len() >= 5 => {
+            // scopes [--waveform <file>] [--vectorscope <file>] <frames...>
+            let mut waveform = None;
+            let mut vectorscope = None;
+            let mut rest = args[2..].iter().peekable();
+            while let Some(option) = rest.next_if(|arg| arg.starts_with("--")) {
+                let file = rest
+                    .next()
+                    .unwrap_or_else(|| panic!("{} expects a file!", option));
+                match option.as_str() {
+                    "--waveform" => waveform = Some(file),
+                    "--vectorscope" => vectorscope = Some(file),
+                    _ => panic!("Unexpected scopes option given: {}", option),
+                }
+            }
+            if waveform.is_none() && vectorscope.is_none() {
+                panic!("Expected --waveform or --vectorscope for the scopes!");
+            }
+            // frames are read one at a time, only their counts are kept
+            let mut scopes: Option<scopes::Scopes> = None;
+            for file in rest {
+                let frame = parse_ppm(file).unwrap_or_else(|error| panic!("{}", error));
+                let scopes = match &mut scopes {
+                    Some(scopes) => scopes,
+                    None => scopes.insert(scopes::Scopes::new(frame.width)?),
+                };
+                scopes
+                    .add(&frame)
+                    .unwrap_or_else(|error| panic!("{}: {}", file, error));
+            }
+            let scopes = scopes.unwrap_or_else(|| panic!("Expected frames for the scopes!"));
+            if let Some(file) = waveform {
+                scopes.export_waveform(file)?;
+            }
+            if let Some(file) = vectorscope {
+                scopes.export_vectorscope(file)?;
+            }
+            return Ok(());
+        }
+        "similar" if args.len() == 4 => {
+            similar::find_similar(&args[2], &args[3], 10)
+                .unwrap_or_else(|error| panic!("{}", error));
+            return Ok(());
         }
+        _ => {}
     }
 
     let out = Path::new(&args[1]);
-    save_ppm(
-        &ppm,
-        &format!(
-            "{}_new.ppm",
-            out.file_stem()
-                .unwrap()
-                .to_os_string()
-                .into_string()
-                .unwrap()
-        ),
-    )?;
+    let loaded = load_image(&args[1]).unwrap_or_else(|error| panic!("{}", error));
+    // images with an alpha channel are written as PAM to keep it
+    let extension = match &loaded {
+        Loaded::Packed(packed) if packed.alpha.is_some() => "pam",
+        _ => "ppm",
+    };
+    let output = format!(
+        "{}_new.{}",
+        out.file_stem()
+            .unwrap()
+            .to_os_string()
+            .into_string()
+            .unwrap(),
+        extension
+    );
+    let mut ops = args[2..].iter();
+    let mut ppm = match loaded {
+        Loaded::Packed(mut packed) => {
+            // steps that only move pixels around run on the packed image, it is
+            // unpacked when the first step needing floats comes up
+            while ops
+                .as_slice()
+                .first()
+                .is_some_and(|arg| packed.apply_step(arg))
+            {
+                ops.next();
+            }
+            if ops.as_slice().is_empty() {
+                save_packed(&packed, &output)?;
+                return Ok(());
+            }
+            packed.unpack()
+        }
+        Loaded::Float(image) => image,
+    };
+    if run_pipeline(&mut ppm, &mut ops)? {
+        panic!("Unmatched ] given!");
+    }
+
+    save_output(&ppm, &output)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+
+    fn decode(bytes: &[u8]) -> Result<Loaded, PpmError> {
+        panic::catch_unwind(|| decode_image(bytes))
+            .unwrap_or_else(|_| panic!("decoding {:?} panicked", String::from_utf8_lossy(bytes)))
+    }
+
+    fn ppm(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn corpus_is_rejected_without_panicking() {
+        let corpus = Path::new(file!()).with_file_name("corpus");
+        let mut files = 0;
+        for entry in fs::read_dir(&corpus).unwrap() {
+            let path = entry.unwrap().path();
+            let bytes = fs::read(&path).unwrap();
+            assert!(decode(&bytes).is_err(), "{} decoded", path.display());
+            files += 1;
+        }
+        assert!(files > 0, "no files in {}", corpus.display());
+    }
+
+    #[test]
+    fn overflowing_dimensions_are_rejected() {
+        let max = usize::MAX;
+        for header in [
+            format!("P6 {} {} 255\n", max, max),
+            format!("P6 {} 2 255\n", max / 2 + 1),
+            // fits as a pixel count, not as a count of bytes
+            format!("P6 {} 1 255\n", max / 3 + 1),
+            format!(
+                "P7
+WIDTH {}
+HEIGHT 1
+DEPTH 4
+MAXVAL 255
+TUPLTYPE RGB_ALPHA
+ENDHDR
+",
+                max / 2 + 1
+            ),
+            format!("PF\n{} 1\n-1.0\n", max / 4 + 1),
+        ] {
+            let result = decode(&ppm(&header, &[0; 12]));
+            assert!(
+                matches!(result, Err(PpmError::DimensionOverflow { .. })),
+                "{:?} not rejected as too large",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn numbers_beyond_usize_are_malformed() {
+        let result = decode(&ppm("P6 99999999999999999999999 1 255\n", &[0; 3]));
+        assert!(matches!(result, Err(PpmError::Malformed(_))));
+    }
+
+    // 65536*65536 is too large for a 32 bit usize and merely missing pixel
+    // data on 64 bit targets
+    #[test]
+    fn sizes_beyond_32_bits() {
+        let result = decode(&ppm("P6 65536 65536 255\n", &[0; 3]));
+        if cfg!(target_pointer_width = "32") {
+            assert!(matches!(result, Err(PpmError::DimensionOverflow { .. })));
+        } else {
+            assert!(matches!(result, Err(PpmError::Truncated { .. })));
+        }
+        assert!(pixel_count(65536, 65535).is_ok());
+        let half = 1 << (usize::BITS / 2);
+        assert!(pixel_count(half, half).is_err());
+    }
+
+    #[test]
+    fn steps_building_a_new_image_keep_the_comments() {
+        let mut image = PpmFile::new(3, 2).unwrap();
+        image.max_val = 100;
+        image.add_comment("made by hand");
+        apply_sobel(&mut image, SobelOutput::Normalized);
+        assert_eq!(image.comments, vec!["made by hand"]);
+        assert_eq!(image.max_val, 100);
+
+        let white = parse_color("#ffffff").unwrap();
+        compose::pad(&mut image, 4, 4, &white).unwrap();
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(image.comments, vec!["made by hand"]);
+        assert_eq!(image.max_val, 100);
+    }
+
+    #[test]
+    fn comments_between_header_fields() {
+        for header in [
+            "P6\n# made by hand\n2 1\n255\n",
+            "P6 2# width\n1 255\n",
+            "P6\n2\n# height follows\n# and another\n1\n255\n",
+            "P6 2 1 #max\n255\n",
+        ] {
+            match decode(&ppm(header, &[1, 2, 3, 4, 5, 6])) {
+                Ok(Loaded::Packed(image)) => {
+                    assert_eq!((image.width, image.height), (2, 1), "{:?}", header);
+                    assert_eq!(image.pixels, vec![[1, 2, 3], [4, 5, 6]]);
+                }
+                _ => panic!("{:?} not decoded", header),
+            }
+        }
+    }
+
+    #[test]
+    fn pfm_comments() {
+        for header in ["PF#made by hand\n1 1\n-1.0\n", "PF\n1 # width\n1\n-1.0\n"] {
+            match decode(&ppm(header, &[0; 12])) {
+                Ok(Loaded::Float(image)) => assert_eq!((image.width, image.height), (1, 1)),
+                _ => panic!("{:?} not decoded", header),
+            }
+        }
+    }
+
+    #[test]
+    fn headers_ending_early() {
+        for header in ["P6", "P6 2", "P6 2 1", "P6 2 1 255", "P6 2 1 # to the end"] {
+            assert!(decode(header.as_bytes()).is_err(), "{:?} decoded", header);
+        }
+        assert!(matches!(
+            decode(&ppm("P6 2 1 255\n", &[0; 5])),
+            Err(PpmError::Truncated {
+                expected: 6,
+                found: 5
+            })
+        ));
+    }
+}
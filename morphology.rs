@@ -0,0 +1,110 @@
+use crate::kernel::Kernel;
+use crate::parallel::for_each_row;
+use crate::PpmFile;
+
+// Odd sized mask of the neighbours taking part, stored row by row
+pub struct StructuringElement {
+    pub width: usize,
+    pub height: usize,
+    pub mask: Vec<bool>,
+}
+
+impl StructuringElement {
+    // `square`, `disk` or `cross` with an optional radius (e.g. "disk,3"), or
+    // the mask laid out like a kernel where nonzero entries take part
+    // (e.g. "0,1,0;1,1,1;0,1,0")
+    pub fn parse(spec: &str) -> Result<StructuringElement, String> {
+        let (shape, radius) = match spec.split_once(',') {
+            Some((shape, radius)) => (shape, Some(radius)),
+            None => (spec, None),
+        };
+        if !shape.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let kernel = Kernel::parse(spec)?;
+            return Ok(StructuringElement {
+                width: kernel.width,
+                height: kernel.height,
+                mask: kernel.weights.iter().map(|&weight| weight != 0.0).collect(),
+            });
+        }
+
+        let radius = match radius {
+            Some(radius) => radius
+                .trim()
+                .parse::<usize>()
+                .map_err(|error| format!("Bad structuring element radius {}: {}", radius, error))?,
+            None => 1,
+        };
+        let r = radius as isize;
+        let member: fn(isize, isize, isize) -> bool = match shape {
+            "square" => |_, _, _| true,
+            "disk" => |x, y, r| x * x + y * y <= r * r,
+            "cross" => |x, y, _| x == 0 || y == 0,
+            _ => return Err(format!("Unknown structuring element: {}", shape)),
+        };
+        let size = 2 * radius + 1;
+        let mut mask = Vec::with_capacity(size * size);
+        for y in -r..=r {
+            for x in -r..=r {
+                mask.push(member(x, y, r));
+            }
+        }
+        Ok(StructuringElement {
+            width: size,
+            height: size,
+            mask,
+        })
+    }
+}
+
+// Every channel becomes the minimum (erosion) or the maximum (dilation) over
+// the neighbours covered by the element. Neighbours outside of the image are
+// skipped, binary images stay binary.
+fn morph(image: &mut PpmFile, element: &StructuringElement, erode: bool) {
+    let width = image.width;
+    let height = image.height;
+    let rx = element.width / 2;
+    let ry = element.height / 2;
+    let pixels = image.pixels.clone();
+    let pick = if erode { f32::min } else { f32::max };
+    let start = if erode {
+        f32::INFINITY
+    } else {
+        f32::NEG_INFINITY
+    };
+
+    for_each_row(&mut image.pixels, width, |y, row| {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let (mut r, mut g, mut b) = (start, start, start);
+            for ey in 0..element.height {
+                // y + ey - ry without underflowing
+                if y + ey < ry || y + ey - ry >= height {
+                    continue;
+                }
+                for ex in 0..element.width {
+                    if !element.mask[ey * element.width + ex] || x + ex < rx || x + ex - rx >= width
+                    {
+                        continue;
+                    }
+                    let p = &pixels[(y + ey - ry) * width + x + ex - rx];
+                    r = pick(r, p.r);
+                    g = pick(g, p.g);
+                    b = pick(b, p.b);
+                }
+            }
+            // an empty element leaves the pixel alone
+            if r.is_finite() {
+                pixel.r = r;
+                pixel.g = g;
+                pixel.b = b;
+            }
+        }
+    });
+}
+
+pub fn apply_erosion(image: &mut PpmFile, element: &StructuringElement) {
+    morph(image, element, true);
+}
+
+pub fn apply_dilation(image: &mut PpmFile, element: &StructuringElement) {
+    morph(image, element, false);
+}
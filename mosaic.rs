@@ -0,0 +1,91 @@
+use std::fs;
+
+use crate::{parse_ppm, Pixel, PpmFile};
+
+fn average(image: &PpmFile, x0: usize, y0: usize, x1: usize, y1: usize) -> Pixel {
+    let mut sum = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+    };
+    for y in y0..y1 {
+        for pixel in &image.pixels[y * image.width + x0..y * image.width + x1] {
+            sum.r += pixel.r;
+            sum.g += pixel.g;
+            sum.b += pixel.b;
+        }
+    }
+    let count = ((x1 - x0) * (y1 - y0)) as f32;
+    Pixel {
+        r: sum.r / count,
+        g: sum.g / count,
+        b: sum.b / count,
+    }
+}
+
+pub struct Tile {
+    image: PpmFile,
+    average: Pixel,
+}
+
+// Every .ppm file of the directory, in name order
+pub fn load_tiles(dir: &str) -> Result<Vec<Tile>, String> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .map_err(|error| format!("Could not read tile directory {}: {}", dir, error))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ppm"))
+        .collect();
+    paths.sort();
+
+    let mut tiles = Vec::new();
+    for path in paths {
+        let image = parse_ppm(&path.to_string_lossy()).map_err(|error| error.to_string())?;
+        if image.pixels.is_empty() {
+            continue;
+        }
+        let average = average(&image, 0, 0, image.width, image.height);
+        tiles.push(Tile { image, average });
+    }
+    if tiles.is_empty() {
+        return Err(format!("No tiles found in {}!", dir));
+    }
+    Ok(tiles)
+}
+
+// Replaces every cell*cell block with its average color or, when tiles are
+// given, with the tile closest to that average scaled to the block
+pub fn apply_mosaic(image: &mut PpmFile, cell: usize, tiles: &[Tile]) -> Result<(), String> {
+    if cell == 0 {
+        return Err("The mosaic cell size should be positive!".to_string());
+    }
+    for y0 in (0..image.height).step_by(cell) {
+        let y1 = usize::min(y0 + cell, image.height);
+        for x0 in (0..image.width).step_by(cell) {
+            let x1 = usize::min(x0 + cell, image.width);
+            let avg = average(image, x0, y0, x1, y1);
+            let tile = tiles.iter().min_by(|a, b| {
+                let da = (a.average.r - avg.r).powi(2)
+                    + (a.average.g - avg.g).powi(2)
+                    + (a.average.b - avg.b).powi(2);
+                let db = (b.average.r - avg.r).powi(2)
+                    + (b.average.g - avg.g).powi(2)
+                    + (b.average.b - avg.b).powi(2);
+                da.total_cmp(&db)
+            });
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    image.pixels[y * image.width + x] = match tile {
+                        // nearest neighbour sample of the whole tile over the full cell
+                        Some(tile) => {
+                            let tx = (x - x0) * tile.image.width / cell;
+                            let ty = (y - y0) * tile.image.height / cell;
+                            tile.image.pixels[ty * tile.image.width + tx].clone()
+                        }
+                        None => avg.clone(),
+                    };
+                }
+            }
+        }
+    }
+    Ok(())
+}
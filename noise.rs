@@ -0,0 +1,25 @@
+use crate::rng::Rng;
+use crate::PpmFile;
+
+// Adds zero mean gaussian noise with the given standard deviation to every channel
+pub fn apply_gaussian_noise(image: &mut PpmFile, sigma: f32, seed: u64) {
+    let mut rng = Rng::new(seed);
+    for pixel in &mut image.pixels {
+        pixel.r = (pixel.r + rng.next_gaussian() * sigma).clamp(0.0, 1.0);
+        pixel.g = (pixel.g + rng.next_gaussian() * sigma).clamp(0.0, 1.0);
+        pixel.b = (pixel.b + rng.next_gaussian() * sigma).clamp(0.0, 1.0);
+    }
+}
+
+// Turns the given fraction of the pixels black or white
+pub fn apply_salt_and_pepper(image: &mut PpmFile, amount: f32, seed: u64) {
+    let mut rng = Rng::new(seed);
+    for pixel in &mut image.pixels {
+        if rng.next_f32() < amount {
+            let val = if rng.next_f32() < 0.5 { 0.0 } else { 1.0 };
+            pixel.r = val;
+            pixel.g = val;
+            pixel.b = val;
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::io;
+
+use crate::colorspace::rgb_to_lab;
+use crate::{report, save_ppm, to_byte, Pixel, PpmFile};
+
+// At most this many pixels take part in the clustering
+const MAX_SAMPLES: usize = 20000;
+const ITERATIONS: usize = 20;
+
+fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+pub struct Swatch {
+    pub color: Pixel,
+    // fraction of the sampled pixels belonging to this color
+    pub share: f32,
+}
+
+// k-means in Lab space, the swatches are sorted from the most to the least common
+pub fn dominant_colors(image: &PpmFile, count: usize) -> Result<Vec<Swatch>, String> {
+    if count < 1 {
+        return Err(format!(
+            "Palette needs a count of at least 1 but {} given!",
+            count
+        ));
+    }
+    let step = usize::max(image.pixels.len() / MAX_SAMPLES, 1);
+    let samples: Vec<&Pixel> = image.pixels.iter().step_by(step).collect();
+    let labs: Vec<[f32; 3]> = samples
+        .iter()
+        .map(|p| {
+            let (l, a, b) = rgb_to_lab(p);
+            [l, a, b]
+        })
+        .collect();
+    let count = usize::min(count, labs.len());
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    // deterministic start, evenly spaced lightness quantiles
+    let mut order: Vec<usize> = (0..labs.len()).collect();
+    order.sort_by(|&a, &b| labs[a][0].total_cmp(&labs[b][0]));
+    let mut centers: Vec<[f32; 3]> = (0..count)
+        .map(|k| labs[order[(2 * k + 1) * labs.len() / (2 * count)]])
+        .collect();
+
+    let mut assignment = vec![0usize; labs.len()];
+    for _ in 0..ITERATIONS {
+        let mut changed = false;
+        for (i, lab) in labs.iter().enumerate() {
+            let mut best = 0;
+            for k in 1..count {
+                if distance(lab, &centers[k]) < distance(lab, &centers[best]) {
+                    best = k;
+                }
+            }
+            if assignment[i] != best {
+                assignment[i] = best;
+                changed = true;
+            }
+        }
+
+        let mut sums = vec![[0.0f32; 3]; count];
+        let mut sizes = vec![0usize; count];
+        for (lab, &k) in labs.iter().zip(&assignment) {
+            for c in 0..3 {
+                sums[k][c] += lab[c];
+            }
+            sizes[k] += 1;
+        }
+        for k in 0..count {
+            if sizes[k] > 0 {
+                for c in 0..3 {
+                    centers[k][c] = sums[k][c] / sizes[k] as f32;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // report the mean RGB of every cluster rather than converting back from Lab
+    let mut swatches: Vec<Swatch> = (0..count)
+        .map(|k| {
+            let members: Vec<&&Pixel> = samples
+                .iter()
+                .zip(&assignment)
+                .filter(|(_, &a)| a == k)
+                .map(|(p, _)| p)
+                .collect();
+            let size = usize::max(members.len(), 1) as f32;
+            Swatch {
+                color: Pixel {
+                    r: members.iter().map(|p| p.r).sum::<f32>() / size,
+                    g: members.iter().map(|p| p.g).sum::<f32>() / size,
+                    b: members.iter().map(|p| p.b).sum::<f32>() / size,
+                },
+                share: members.len() as f32 / samples.len() as f32,
+            }
+        })
+        .filter(|swatch| swatch.share > 0.0)
+        .collect();
+    swatches.sort_by(|a, b| b.share.total_cmp(&a.share));
+    Ok(swatches)
+}
+
+pub fn to_hex(pixel: &Pixel) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        to_byte(pixel.r),
+        to_byte(pixel.g),
+        to_byte(pixel.b)
+    )
+}
+
+// Writes the swatches as a strip of 64*64 squares and prints them as JSON
+pub fn export_palette(image: &PpmFile, name: &str, count: usize) -> std::io::Result<()> {
+    let swatches = dominant_colors(image, count).map_err(io::Error::other)?;
+    let mut strip = PpmFile::new(64 * swatches.len(), 64).map_err(io::Error::other)?;
+    for (i, pixel) in strip.pixels.iter_mut().enumerate() {
+        *pixel = swatches[(i % strip.width) / 64].color.clone();
+    }
+    save_ppm(&strip, name)?;
+
+    let entries: Vec<String> = swatches
+        .iter()
+        .map(|swatch| {
+            format!(
+                "{{\"hex\": \"{}\", \"share\": {:.4}}}",
+                to_hex(&swatch.color),
+                swatch.share
+            )
+        })
+        .collect();
+    report(&format!("{{\"colors\": [{}]}}\n", entries.join(", ")));
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use std::fs::File;
+use std::io::Write;
+
+use crate::packed::PackedImage;
+use crate::{decoded_pixel_count, header_comments, PpmError};
+
+pub struct PamHeader {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    pub max_val: usize,
+    pub tuple_type: String,
+    // offset of the first byte after ENDHDR
+    pub data: usize,
+}
+
+// The header is a line per field, ending with ENDHDR
+pub fn read_header(bytes: &[u8]) -> Result<PamHeader, PpmError> {
+    let mut width = None;
+    let mut height = None;
+    let mut depth = None;
+    let mut max_val = None;
+    let mut tuple_type = String::new();
+
+    let mut offset = 0;
+    let mut lines = 0;
+    loop {
+        let end = match bytes[offset..].iter().position(|&b| b == b'\n') {
+            Some(end) => offset + end,
+            None => return Err(PpmError::Malformed("PAM header ends early".to_string())),
+        };
+        let line = String::from_utf8_lossy(&bytes[offset..end]);
+        offset = end + 1;
+        lines += 1;
+        let line = line.trim();
+        if lines == 1 || line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (field, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let value = value.trim();
+        let number = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| PpmError::Malformed(format!("PAM {} not a number: {}", field, value)))
+        };
+        match field {
+            "WIDTH" => width = Some(number()?),
+            "HEIGHT" => height = Some(number()?),
+            "DEPTH" => depth = Some(number()?),
+            "MAXVAL" => max_val = Some(number()?),
+            "TUPLTYPE" => tuple_type = value.to_string(),
+            "ENDHDR" => break,
+            _ => return Err(PpmError::Malformed(format!("unknown PAM field {}", field))),
+        }
+    }
+
+    let missing = |field: &str| PpmError::Malformed(format!("PAM header has no {}", field));
+    Ok(PamHeader {
+        width: width.ok_or_else(|| missing("WIDTH"))?,
+        height: height.ok_or_else(|| missing("HEIGHT"))?,
+        depth: depth.ok_or_else(|| missing("DEPTH"))?,
+        max_val: max_val.ok_or_else(|| missing("MAXVAL"))?,
+        tuple_type,
+        data: offset,
+    })
+}
+
+// PAM (P7) with 8 bit samples. Gray tuples are spread over the three channels
+// and the alpha of the _ALPHA tuple types is kept next to the pixels.
+pub fn parse_pam(bytes: &[u8]) -> Result<PackedImage, PpmError> {
+    let PamHeader {
+        width,
+        height,
+        depth,
+        max_val,
+        tuple_type,
+        data: offset,
+    } = read_header(bytes)?;
+    if max_val != 255 {
+        return Err(PpmError::Malformed(format!(
+            "only a maximum value of 255 is supported but {} found",
+            max_val
+        )));
+    }
+    let expected_depth = match tuple_type.as_str() {
+        "GRAYSCALE" => 1,
+        "GRAYSCALE_ALPHA" => 2,
+        "RGB" => 3,
+        "RGB_ALPHA" => 4,
+        _ => {
+            return Err(PpmError::Malformed(format!(
+                "unsupported PAM tuple type {:?}",
+                tuple_type
+            )))
+        }
+    };
+    if depth != expected_depth {
+        return Err(PpmError::Malformed(format!(
+            "{} needs a depth of {} but {} found",
+            tuple_type, expected_depth, depth
+        )));
+    }
+
+    let len = decoded_pixel_count(width, height)?
+        .checked_mul(depth)
+        .ok_or(PpmError::DimensionOverflow { width, height })?;
+    let found = bytes.len() - offset;
+    if found < len {
+        return Err(PpmError::Truncated {
+            expected: len,
+            found,
+        });
+    }
+    if found > len {
+        eprintln!("Ignoring {} bytes after the pixel data", found - len);
+    }
+
+    let tuples = bytes[offset..offset + len].chunks_exact(depth);
+    let pixels = tuples
+        .clone()
+        .map(|tuple| match depth {
+            1 | 2 => [tuple[0]; 3],
+            _ => [tuple[0], tuple[1], tuple[2]],
+        })
+        .collect();
+    let alpha = match depth {
+        2 | 4 => Some(tuples.map(|tuple| tuple[depth - 1]).collect()),
+        _ => None,
+    };
+    Ok(PackedImage {
+        width,
+        height,
+        pixels,
+        alpha,
+        comments: header_comments(&bytes[..offset]),
+    })
+}
+
+// RGB_ALPHA when the image has an alpha channel, RGB otherwise
+pub fn write_pam(image: &PackedImage, name: &str) -> std::io::Result<()> {
+    let mut file = File::create(name)?;
+    let mut header = "P7\n".to_string();
+    for comment in &image.comments {
+        header.push_str(&format!("# {}\n", comment));
+    }
+    let (depth, tuple_type) = match image.alpha {
+        Some(_) => (4, "RGB_ALPHA"),
+        None => (3, "RGB"),
+    };
+    header.push_str(&format!(
+        "WIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL 255\nTUPLTYPE {}\nENDHDR\n",
+        image.width, image.height, depth, tuple_type
+    ));
+    file.write_all(header.as_bytes())?;
+    match &image.alpha {
+        Some(alpha) => {
+            let data: Vec<u8> = image
+                .pixels
+                .iter()
+                .zip(alpha)
+                .flat_map(|(&[r, g, b], &a)| [r, g, b, a])
+                .collect();
+            file.write_all(&data)?;
+        }
+        None => file.write_all(image.pixels.as_flattened())?,
+    }
+    file.sync_all()
+}
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Set by --threads, 0 uses every available core
+static THREADS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_threads(count: usize) {
+    THREADS.store(count, Ordering::Relaxed);
+}
+
+fn threads() -> usize {
+    match THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map_or(1, |count| count.get()),
+        count => count,
+    }
+}
+
+// Rows per band when height rows are split over the threads
+pub fn band_height(height: usize) -> usize {
+    let threads = usize::clamp(threads(), 1, height.max(1));
+    usize::max(height.div_ceil(threads), 1)
+}
+
+// Splits `out` into bands of `band` rows of `width` elements and calls
+// `rows(first_row, elements)` for every band, each on its own thread
+pub fn for_each_band<T, F>(out: &mut [T], width: usize, band: usize, rows: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    if width == 0 || out.is_empty() {
+        return;
+    }
+    let chunk = band.max(1) * width;
+    if out.len() <= chunk {
+        rows(0, out);
+        return;
+    }
+
+    let rows = &rows;
+    thread::scope(|scope| {
+        for (i, elements) in out.chunks_mut(chunk).enumerate() {
+            scope.spawn(move || rows(i * band, elements));
+        }
+    });
+}
+
+// Calls `row(y, elements)` for every row of `width` elements, the rows being
+// split into one band per thread
+pub fn for_each_row<T, F>(out: &mut [T], width: usize, row: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    if width == 0 {
+        return;
+    }
+    let band = band_height(out.len() / width);
+    for_each_band(out, width, band, |first, elements| {
+        for (y, elements) in elements.chunks_mut(width).enumerate() {
+            row(first + y, elements);
+        }
+    });
+}
@@ -0,0 +1,101 @@
+use crate::{decoded_pixel_count, is_space, next_token, Pixel, PpmError, PpmFile};
+
+fn header_value<T: std::str::FromStr>(
+    bytes: &[u8],
+    offset: &mut usize,
+    name: &str,
+) -> Result<T, PpmError> {
+    let token = next_token(bytes, offset)?;
+    token
+        .parse::<T>()
+        .map_err(|_| PpmError::Malformed(format!("PFM {} not a number: {}", name, token)))
+}
+
+// Floating point PFM (PF for color, Pf for grayscale). The values are kept as
+// they are, so HDR data goes beyond 1 until it is tone mapped.
+pub fn parse_pfm(bytes: &[u8]) -> Result<PpmFile, PpmError> {
+    let mut offset = 0;
+    // comments are skipped the same way as in PPM headers, even right after the magic
+    let channels = match next_token(bytes, &mut offset)? {
+        "PF" => 3,
+        _ => 1,
+    };
+    let width: usize = header_value(bytes, &mut offset, "width")?;
+    let height: usize = header_value(bytes, &mut offset, "height")?;
+    // the sign gives the byte order, negative being little endian
+    let scale: f32 = header_value(bytes, &mut offset, "scale")?;
+    if !scale.is_finite() || scale == 0.0 {
+        return Err(PpmError::Malformed(format!(
+            "PFM scale {} is invalid",
+            scale
+        )));
+    }
+    // a single whitespace separates the header from the data
+    match bytes.get(offset) {
+        Some(&b) if is_space(b) => offset += 1,
+        _ => {
+            return Err(PpmError::Malformed(
+                "the PFM header should end with a whitespace".to_string(),
+            ))
+        }
+    }
+
+    let count = decoded_pixel_count(width, height)?;
+    let len = count
+        .checked_mul(channels * 4)
+        .ok_or(PpmError::DimensionOverflow { width, height })?;
+    let data = bytes.get(offset..).unwrap_or(&[]);
+    if data.len() < len {
+        return Err(PpmError::Truncated {
+            expected: len,
+            found: data.len(),
+        });
+    }
+
+    let samples: Vec<f32> = data[..len]
+        .chunks_exact(4)
+        .map(|chunk| {
+            let chunk = [chunk[0], chunk[1], chunk[2], chunk[3]];
+            if scale < 0.0 {
+                f32::from_le_bytes(chunk)
+            } else {
+                f32::from_be_bytes(chunk)
+            }
+        })
+        .collect();
+    if samples.iter().any(|sample| !sample.is_finite()) {
+        return Err(PpmError::Malformed(
+            "PFM data holds infinite or NaN values".to_string(),
+        ));
+    }
+
+    // rows are stored from the bottom up
+    let mut pixels = Vec::with_capacity(count);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let i = (y * width + x) * channels;
+            pixels.push(if channels == 3 {
+                Pixel {
+                    r: samples[i],
+                    g: samples[i + 1],
+                    b: samples[i + 2],
+                }
+            } else {
+                Pixel {
+                    r: samples[i],
+                    g: samples[i],
+                    b: samples[i],
+                }
+            });
+        }
+    }
+
+    Ok(PpmFile {
+        width,
+        height,
+        max_val: 255,
+        alpha: None,
+        comments: Vec::new(),
+        pixels,
+    })
+}
@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::atomic::Ordering;
+
+use crate::packed::PackedImage;
+use crate::tiled::read_header;
+use crate::{encode_output, ppm_header, run_pipeline, PpmError, REPORTS_TO_STDERR};
+
+// Reads P6 frames from stdin until it ends and writes every frame through the
+// steps to stdout, e.g. between two ffmpeg processes using image2pipe
+pub fn run(steps: &[String]) -> Result<(), Box<dyn Error>> {
+    // reports such as stats would end up in the middle of the frames
+    REPORTS_TO_STDERR.store(true, Ordering::Relaxed);
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut output = BufWriter::new(io::stdout().lock());
+    let mut frame = 0;
+    // the stream may only end between frames
+    while !input.fill_buf()?.is_empty() {
+        let (width, height, comments) = read_header(&mut input)?;
+        let len = crate::decoded_pixel_count(width, height)?
+            .checked_mul(3)
+            .ok_or(PpmError::DimensionOverflow { width, height })?;
+        let mut bytes = vec![0u8; len];
+        input.read_exact(&mut bytes).map_err(|_| {
+            format!(
+                "Frame {} is truncated, expected {} bytes of pixel data!",
+                frame, len
+            )
+        })?;
+
+        let mut image = PackedImage {
+            width,
+            height,
+            pixels: bytes
+                .chunks_exact(3)
+                .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+                .collect(),
+            alpha: None,
+            comments,
+        }
+        .unpack();
+        if run_pipeline(&mut image, &mut steps.iter())? {
+            return Err("Unmatched ] given!".into());
+        }
+
+        let encoded = encode_output(&image, frame);
+        output
+            .write_all(ppm_header(encoded.width, encoded.height, &encoded.comments).as_bytes())?;
+        output.write_all(encoded.pixels.as_flattened())?;
+        output.flush()?;
+        frame += 1;
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use crate::{Pixel, PpmFile};
+
+fn channel(pixel: &Pixel, c: usize) -> f32 {
+    match c {
+        0 => pixel.r,
+        1 => pixel.g,
+        _ => pixel.b,
+    }
+}
+
+// (channel, range) of the widest channel in the box
+fn widest_channel(colors: &[Pixel]) -> (usize, f32) {
+    (0..3)
+        .map(|c| {
+            let min = colors
+                .iter()
+                .map(|p| channel(p, c))
+                .fold(f32::MAX, f32::min);
+            let max = colors
+                .iter()
+                .map(|p| channel(p, c))
+                .fold(f32::MIN, f32::max);
+            (c, max - min)
+        })
+        .fold(
+            (0, -1.0),
+            |best, next| if next.1 > best.1 { next } else { best },
+        )
+}
+
+// Splits the color box with the widest channel range at its median until
+// there are `count` boxes, the palette being the mean of every box
+pub fn median_cut(image: &PpmFile, count: usize) -> Vec<Pixel> {
+    let mut boxes: Vec<Vec<Pixel>> = vec![image.pixels.clone()];
+    while boxes.len() < count {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, widest_channel(colors)))
+            .max_by(|a, b| (a.1).1.total_cmp(&(b.1).1));
+        let (i, (c, range)) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+        if range <= 0.0 {
+            break;
+        }
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_by(|a, b| channel(a, c).total_cmp(&channel(b, c)));
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .filter(|colors| !colors.is_empty())
+        .map(|colors| {
+            let n = colors.len() as f32;
+            Pixel {
+                r: colors.iter().map(|p| p.r).sum::<f32>() / n,
+                g: colors.iter().map(|p| p.g).sum::<f32>() / n,
+                b: colors.iter().map(|p| p.b).sum::<f32>() / n,
+            }
+        })
+        .collect()
+}
+
+fn nearest(palette: &[Pixel], pixel: &Pixel) -> Pixel {
+    palette
+        .iter()
+        .min_by(|a, b| {
+            let da = (a.r - pixel.r).powi(2) + (a.g - pixel.g).powi(2) + (a.b - pixel.b).powi(2);
+            let db = (b.r - pixel.r).powi(2) + (b.g - pixel.g).powi(2) + (b.b - pixel.b).powi(2);
+            da.total_cmp(&db)
+        })
+        .cloned()
+        .unwrap_or_else(|| pixel.clone())
+}
+
+// Maps every pixel to the closest palette color, optionally spreading the
+// error over the neighbours with Floyd-Steinberg
+pub fn apply_palette(image: &mut PpmFile, palette: &[Pixel], dither: bool) {
+    let width = image.width;
+    for i in 0..image.pixels.len() {
+        let old = image.pixels[i].clone();
+        let new = nearest(palette, &old);
+        if dither {
+            let x = i % width;
+            let error = [old.r - new.r, old.g - new.g, old.b - new.b];
+            let mut spread = |j: usize, weight: f32| {
+                if let Some(p) = image.pixels.get_mut(j) {
+                    p.r += error[0] * weight;
+                    p.g += error[1] * weight;
+                    p.b += error[2] * weight;
+                }
+            };
+            if x + 1 < width {
+                spread(i + 1, 7.0 / 16.0);
+                spread(i + width + 1, 1.0 / 16.0);
+            }
+            if x > 0 {
+                spread(i + width - 1, 3.0 / 16.0);
+            }
+            spread(i + width, 5.0 / 16.0);
+        }
+        image.pixels[i] = new;
+    }
+}
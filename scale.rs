@@ -0,0 +1,298 @@
+use crate::{allocated_pixel_count, Pixel, PpmError, PpmFile};
+
+#[derive(Clone, Copy, Debug)]
+pub enum ScaleFilter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos,
+}
+
+impl ScaleFilter {
+    pub fn parse(name: &str) -> Result<ScaleFilter, String> {
+        match name {
+            "nearest" => Ok(ScaleFilter::Nearest),
+            "bilinear" => Ok(ScaleFilter::Bilinear),
+            "bicubic" => Ok(ScaleFilter::Bicubic),
+            "lanczos" => Ok(ScaleFilter::Lanczos),
+            _ => Err(format!("Unknown scale filter: {}", name)),
+        }
+    }
+}
+
+// A zero width or height follows the aspect ratio of the image
+pub fn target_size(image: &PpmFile, width: usize, height: usize) -> Result<(usize, usize), String> {
+    let (width, height) = match (width, height) {
+        (0, 0) => return Err("Scaling needs a width or a height!".to_string()),
+        (0, h) => (
+            usize::max(
+                (image.width as f32 * h as f32 / image.height as f32).round() as usize,
+                1,
+            ),
+            h,
+        ),
+        (w, 0) => (
+            w,
+            usize::max(
+                (image.height as f32 * w as f32 / image.width as f32).round() as usize,
+                1,
+            ),
+        ),
+        size => size,
+    };
+    if image.width == 0 || image.height == 0 {
+        return Err("Cannot scale an empty image!".to_string());
+    }
+    Ok((width, height))
+}
+
+// Source coordinate of the center of a destination pixel
+fn source_position(pos: usize, from: usize, to: usize) -> f32 {
+    (pos as f32 + 0.5) * from as f32 / to as f32 - 0.5
+}
+
+// Every pixel takes the color of the closest source pixel, keeping hard edges
+pub fn scale_nearest(image: &mut PpmFile, width: usize, height: usize) -> Result<(), PpmError> {
+    let mut pixels = Vec::with_capacity(allocated_pixel_count(width, height)?);
+    for y in 0..height {
+        let sy = source_position(y, image.height, height)
+            .round()
+            .clamp(0.0, (image.height - 1) as f32) as usize;
+        for x in 0..width {
+            let sx = source_position(x, image.width, width)
+                .round()
+                .clamp(0.0, (image.width - 1) as f32) as usize;
+            pixels.push(image.pixels[sy * image.width + sx].clone());
+        }
+    }
+    image.width = width;
+    image.height = height;
+    image.pixels = pixels;
+    Ok(())
+}
+
+// Source index and weight of the next source pixel, clamped to the edges
+fn linear_taps(pos: usize, from: usize, to: usize) -> (usize, usize, f32) {
+    let s = source_position(pos, from, to).clamp(0.0, (from - 1) as f32);
+    let first = s.floor() as usize;
+    (first, usize::min(first + 1, from - 1), s - first as f32)
+}
+
+// Interpolates between the four closest source pixels
+pub fn scale_bilinear(image: &mut PpmFile, width: usize, height: usize) -> Result<(), PpmError> {
+    let mut pixels = Vec::with_capacity(allocated_pixel_count(width, height)?);
+    for y in 0..height {
+        let (y0, y1, fy) = linear_taps(y, image.height, height);
+        for x in 0..width {
+            let (x0, x1, fx) = linear_taps(x, image.width, width);
+            let at = |x: usize, y: usize| &image.pixels[y * image.width + x];
+            let weights = [
+                (at(x0, y0), (1.0 - fx) * (1.0 - fy)),
+                (at(x1, y0), fx * (1.0 - fy)),
+                (at(x0, y1), (1.0 - fx) * fy),
+                (at(x1, y1), fx * fy),
+            ];
+            let mut pixel = Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            };
+            for (p, weight) in &weights {
+                pixel.r += p.r * weight;
+                pixel.g += p.g * weight;
+                pixel.b += p.b * weight;
+            }
+            pixels.push(pixel);
+        }
+    }
+    image.width = width;
+    image.height = height;
+    image.pixels = pixels;
+    Ok(())
+}
+
+// Keys cubic convolution kernel with a = -0.5 (Catmull-Rom)
+fn cubic(t: f32) -> f32 {
+    let t = t.abs();
+    if t < 1.0 {
+        (1.5 * t - 2.5) * t * t + 1.0
+    } else if t < 2.0 {
+        ((-0.5 * t + 2.5) * t - 4.0) * t + 2.0
+    } else {
+        0.0
+    }
+}
+
+// Source indices and weights of the four closest source pixels, clamped to the edges
+fn cubic_taps(pos: usize, from: usize, to: usize) -> [(usize, f32); 4] {
+    let s = source_position(pos, from, to);
+    let first = s.floor();
+    let mut taps = [(0, 0.0); 4];
+    for (i, tap) in taps.iter_mut().enumerate() {
+        let src = first + i as f32 - 1.0;
+        *tap = (src.clamp(0.0, (from - 1) as f32) as usize, cubic(s - src));
+    }
+    taps
+}
+
+// Weighs the 4*4 closest source pixels, sharper than bilinear for photos.
+// The overshoot around edges is clamped.
+pub fn scale_bicubic(image: &mut PpmFile, width: usize, height: usize) -> Result<(), PpmError> {
+    let mut pixels = Vec::with_capacity(allocated_pixel_count(width, height)?);
+    for y in 0..height {
+        let rows = cubic_taps(y, image.height, height);
+        for x in 0..width {
+            let columns = cubic_taps(x, image.width, width);
+            let mut pixel = Pixel {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            };
+            for &(sy, wy) in &rows {
+                for &(sx, wx) in &columns {
+                    let p = &image.pixels[sy * image.width + sx];
+                    pixel.r += p.r * wx * wy;
+                    pixel.g += p.g * wx * wy;
+                    pixel.b += p.b * wx * wy;
+                }
+            }
+            pixel.r = pixel.r.clamp(0.0, 1.0);
+            pixel.g = pixel.g.clamp(0.0, 1.0);
+            pixel.b = pixel.b.clamp(0.0, 1.0);
+            pixels.push(pixel);
+        }
+    }
+    image.width = width;
+    image.height = height;
+    image.pixels = pixels;
+    Ok(())
+}
+
+fn lanczos3(t: f32) -> f32 {
+    let t = t.abs();
+    if t < f32::EPSILON {
+        1.0
+    } else if (t - t.round()).abs() < 1e-4 {
+        // exact zeros at the other integers, so scaling to the same size is lossless
+        0.0
+    } else if t < 3.0 {
+        let pt = std::f32::consts::PI * t;
+        3.0 * pt.sin() * (pt / 3.0).sin() / (pt * pt)
+    } else {
+        0.0
+    }
+}
+
+// Normalized Lanczos-3 taps of every destination position. When shrinking, the
+// kernel is stretched by the reduction factor so it low passes the source
+// instead of aliasing.
+fn lanczos_taps(from: usize, to: usize) -> Vec<Vec<(usize, f32)>> {
+    let stretch = f32::max(from as f32 / to as f32, 1.0);
+    let support = 3.0 * stretch;
+    (0..to)
+        .map(|pos| {
+            let s = source_position(pos, from, to);
+            let first = (s - support).ceil() as isize;
+            let last = (s + support).floor() as isize;
+            let mut taps: Vec<(usize, f32)> = (first..=last)
+                .map(|src| {
+                    let index = src.clamp(0, from as isize - 1) as usize;
+                    (index, lanczos3((s - src as f32) / stretch))
+                })
+                .collect();
+            let total: f32 = taps.iter().map(|tap| tap.1).sum();
+            for tap in &mut taps {
+                tap.1 /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+// Separable Lanczos-3 resampling, first along the rows then the columns
+pub fn scale_lanczos(image: &mut PpmFile, width: usize, height: usize) -> Result<(), PpmError> {
+    // sum of the weighted pixels, source(i) being the pixel of tap i
+    fn weigh<'a>(source: impl Fn(usize) -> &'a Pixel, taps: &[(usize, f32)]) -> Pixel {
+        let mut pixel = Pixel {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+        };
+        for &(i, weight) in taps {
+            let tap = source(i);
+            pixel.r += tap.r * weight;
+            pixel.g += tap.g * weight;
+            pixel.b += tap.b * weight;
+        }
+        pixel
+    }
+
+    // both sizes are checked before the taps, which grow with the target too
+    let wide_len = allocated_pixel_count(width, image.height)?;
+    let len = allocated_pixel_count(width, height)?;
+    let columns = lanczos_taps(image.width, width);
+    let mut wide = Vec::with_capacity(wide_len);
+    for row in image.pixels.chunks(image.width) {
+        wide.extend(columns.iter().map(|taps| weigh(|i| &row[i], taps)));
+    }
+
+    let rows = lanczos_taps(image.height, height);
+    let mut pixels = Vec::with_capacity(len);
+    for taps in &rows {
+        for x in 0..width {
+            let mut pixel = weigh(|y| &wide[y * width + x], taps);
+            pixel.r = pixel.r.clamp(0.0, 1.0);
+            pixel.g = pixel.g.clamp(0.0, 1.0);
+            pixel.b = pixel.b.clamp(0.0, 1.0);
+            pixels.push(pixel);
+        }
+    }
+    image.width = width;
+    image.height = height;
+    image.pixels = pixels;
+    Ok(())
+}
+
+pub fn scale(
+    image: &mut PpmFile,
+    width: usize,
+    height: usize,
+    filter: ScaleFilter,
+) -> Result<(), PpmError> {
+    match filter {
+        ScaleFilter::Nearest => scale_nearest(image, width, height),
+        ScaleFilter::Bilinear => scale_bilinear(image, width, height),
+        ScaleFilter::Bicubic => scale_bicubic(image, width, height),
+        ScaleFilter::Lanczos => scale_lanczos(image, width, height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_targets_are_an_error() {
+        let mut image = PpmFile::new(2, 2).unwrap();
+        for (width, height) in [
+            (2_000_000_000, 2_000_000_000),
+            (100_000_000, 100),
+            (usize::MAX, 2),
+        ] {
+            for filter in [
+                ScaleFilter::Nearest,
+                ScaleFilter::Bilinear,
+                ScaleFilter::Bicubic,
+                ScaleFilter::Lanczos,
+            ] {
+                assert!(matches!(
+                    scale(&mut image, width, height, filter),
+                    Err(PpmError::DimensionOverflow { .. })
+                ));
+            }
+        }
+        assert_eq!((image.width, image.height), (2, 2));
+        scale(&mut image, 3, 5, ScaleFilter::Lanczos).unwrap();
+        assert_eq!(image.pixels.len(), 15);
+    }
+}
@@ -0,0 +1,87 @@
+use std::io;
+
+use crate::colorspace::rgb_to_ycbcr;
+use crate::{luminance, pixel_count, save_ppm, PpmFile};
+
+// Turns hit counts into a log scaled grayscale image
+fn counts_to_image(counts: &[u32], width: usize, height: usize) -> PpmFile {
+    let max = *counts.iter().max().unwrap_or(&0) as f32;
+    // the counts were allocated with the same dimensions
+    let mut image = PpmFile::new(width, height).unwrap();
+    for (pixel, &count) in image.pixels.iter_mut().zip(counts) {
+        let val = if max > 0.0 {
+            (1.0 + count as f32).ln() / (1.0 + max).ln()
+        } else {
+            0.0
+        };
+        pixel.r = val;
+        pixel.g = val;
+        pixel.b = val;
+    }
+    image
+}
+
+// Hit counts of the scopes, summed over every frame added so a sequence ends
+// up averaged
+pub struct Scopes {
+    width: usize,
+    waveform: Vec<u32>,
+    vectorscope: Vec<u32>,
+}
+
+impl Scopes {
+    pub fn new(width: usize) -> io::Result<Scopes> {
+        Ok(Scopes {
+            width,
+            waveform: vec![0u32; pixel_count(width, 256).map_err(io::Error::other)?],
+            vectorscope: vec![0u32; 256 * 256],
+        })
+    }
+
+    // Frames of a sequence have to be equally wide for the waveform columns to line up
+    pub fn add(&mut self, image: &PpmFile) -> Result<(), String> {
+        if image.width != self.width {
+            return Err(format!(
+                "Scopes of a sequence need frames of the same width but {} and {} given!",
+                self.width, image.width
+            ));
+        }
+        for (i, pixel) in image.pixels.iter().enumerate() {
+            let level = (luminance(pixel).clamp(0.0, 1.0) * 255.0).round() as usize;
+            self.waveform[(255 - level) * self.width + i % self.width] += 1;
+
+            let (_, cb, cr) = rgb_to_ycbcr(pixel);
+            let cb = (cb + 0.5).clamp(0.0, 1.0);
+            let cr = (cr + 0.5).clamp(0.0, 1.0);
+            let col = (cb * 255.0).round() as usize;
+            let row = 255 - (cr * 255.0).round() as usize;
+            self.vectorscope[row * 256 + col] += 1;
+        }
+        Ok(())
+    }
+
+    // One column per image column, luminance grows upwards
+    pub fn export_waveform(&self, name: &str) -> io::Result<()> {
+        save_ppm(&counts_to_image(&self.waveform, self.width, 256), name)
+    }
+
+    // Cb on the horizontal and Cr on the vertical axis, neutral colors end up in the center
+    pub fn export_vectorscope(&self, name: &str) -> io::Result<()> {
+        save_ppm(&counts_to_image(&self.vectorscope, 256, 256), name)
+    }
+}
+
+fn scopes_of(image: &PpmFile) -> io::Result<Scopes> {
+    let mut scopes = Scopes::new(image.width)?;
+    // the width is the image's own
+    scopes.add(image).map_err(io::Error::other)?;
+    Ok(scopes)
+}
+
+pub fn export_waveform(image: &PpmFile, name: &str) -> io::Result<()> {
+    scopes_of(image)?.export_waveform(name)
+}
+
+pub fn export_vectorscope(image: &PpmFile, name: &str) -> io::Result<()> {
+    scopes_of(image)?.export_vectorscope(name)
+}
@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{decode_image, encode_output, ppm_header, reset_settings, run_pipeline, Loaded};
+
+// Images larger than this are refused rather than allocated
+const MAX_IMAGE: u64 = 1 << 30;
+const MAX_STEPS: u32 = 1 << 20;
+
+// Runs the steps (one argument per line) on the encoded image and returns the
+// encoded P6 result
+fn run_job(steps: &str, image: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    // settings of a previous job must not leak into this one
+    reset_settings();
+    let mut image = match decode_image(image)? {
+        Loaded::Packed(packed) => packed.unpack(),
+        Loaded::Float(image) => image,
+    };
+    let steps: Vec<String> = steps
+        .lines()
+        .filter(|step| !step.is_empty())
+        .map(|step| step.to_string())
+        .collect();
+    if run_pipeline(&mut image, &mut steps.iter())? {
+        return Err("Unmatched ] given!".into());
+    }
+    let encoded = encode_output(&image, 0);
+    let mut bytes = ppm_header(encoded.width, encoded.height, &encoded.comments).into_bytes();
+    bytes.extend_from_slice(encoded.pixels.as_flattened());
+    Ok(bytes)
+}
+
+fn read_u32(stream: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    stream.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64(stream: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    stream.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+// Answers jobs until the client hangs up. A job is the length of the steps
+// (u32) and the steps, then the length of the image (u64) and the image, all
+// lengths big endian. The answer is a status byte (0 on success), the length
+// of the payload (u64) and either the P6 result or the error message.
+fn handle(stream: UnixStream) -> io::Result<()> {
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut output = BufWriter::new(stream);
+    loop {
+        let steps_len = match read_u32(&mut input) {
+            Ok(len) => len,
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        if steps_len > MAX_STEPS {
+            return Err(io::Error::other("steps too long"));
+        }
+        let mut steps = vec![0u8; steps_len as usize];
+        input.read_exact(&mut steps)?;
+        let image_len = read_u64(&mut input)?;
+        if image_len > MAX_IMAGE {
+            return Err(io::Error::other("image too large"));
+        }
+        let mut image = vec![0u8; image_len as usize];
+        input.read_exact(&mut image)?;
+
+        let steps = String::from_utf8_lossy(&steps);
+        // filters report bad parameters by panicking, which must not take the
+        // server down
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            run_job(&steps, &image).map_err(|error| error.to_string())
+        }))
+        .unwrap_or_else(|cause| {
+            Err(cause
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| cause.downcast_ref::<&str>().map(|text| text.to_string()))
+                .unwrap_or_else(|| "The job failed!".to_string()))
+        });
+
+        let (status, payload) = match result {
+            Ok(bytes) => (0u8, bytes),
+            Err(message) => (1u8, message.into_bytes()),
+        };
+        output.write_all(&[status])?;
+        output.write_all(&(payload.len() as u64).to_be_bytes())?;
+        output.write_all(&payload)?;
+        output.flush()?;
+    }
+}
+
+// Listens on the socket and serves one client at a time, so every job sees
+// the default settings and has all cores for itself
+pub fn serve(path: &str) -> Result<(), Box<dyn Error>> {
+    // a socket left behind by a previous run would make bind fail
+    if fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    eprintln!("Listening on {}", path);
+    for stream in listener.incoming() {
+        if let Err(error) = stream.and_then(handle) {
+            eprintln!("Connection closed: {}", error);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::{luminance, parse_ppm, PpmFile};
+
+const INDEX_FILE: &str = "ppm_filter.index";
+const HASH_SIZE: usize = 32;
+const BINS: usize = 4;
+
+struct Descriptor {
+    hash: u64,
+    // normalized 4*4*4 RGB histogram
+    histogram: Vec<f32>,
+}
+
+struct Entry {
+    modified: u64,
+    descriptor: Descriptor,
+}
+
+// Luminance averaged down to size*size
+fn shrink(image: &PpmFile, size: usize) -> Vec<f32> {
+    let mut sums = vec![0.0f32; size * size];
+    let mut counts = vec![0u32; size * size];
+    for (i, pixel) in image.pixels.iter().enumerate() {
+        let x = (i % image.width) * size / image.width;
+        let y = (i / image.width) * size / image.height;
+        sums[y * size + x] += luminance(pixel);
+        counts[y * size + x] += 1;
+    }
+    sums.iter()
+        .zip(counts)
+        .map(|(sum, count)| if count > 0 { sum / count as f32 } else { 0.0 })
+        .collect()
+}
+
+// pHash: the 8*8 lowest frequencies of the DCT compared against their median
+fn perceptual_hash(image: &PpmFile) -> u64 {
+    let small = shrink(image, HASH_SIZE);
+    let mut coefficients = Vec::with_capacity(64);
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..HASH_SIZE {
+                for x in 0..HASH_SIZE {
+                    sum += small[y * HASH_SIZE + x]
+                        * f32::cos((2 * x + 1) as f32 * u as f32 * PI / (2 * HASH_SIZE) as f32)
+                        * f32::cos((2 * y + 1) as f32 * v as f32 * PI / (2 * HASH_SIZE) as f32);
+                }
+            }
+            coefficients.push(sum);
+        }
+    }
+
+    // the DC term only reflects the overall brightness
+    let mut sorted: Vec<f32> = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+    coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, &c)| c > median)
+        .fold(0u64, |hash, (i, _)| hash | 1 << i)
+}
+
+fn color_histogram(image: &PpmFile) -> Vec<f32> {
+    let mut histogram = vec![0.0f32; BINS * BINS * BINS];
+    let bin = |val: f32| usize::min((val.clamp(0.0, 1.0) * BINS as f32) as usize, BINS - 1);
+    for pixel in &image.pixels {
+        histogram[(bin(pixel.r) * BINS + bin(pixel.g)) * BINS + bin(pixel.b)] += 1.0;
+    }
+    let total = usize::max(image.pixels.len(), 1) as f32;
+    histogram.iter().map(|count| count / total).collect()
+}
+
+fn describe(image: &PpmFile) -> Descriptor {
+    Descriptor {
+        hash: perceptual_hash(image),
+        histogram: color_histogram(image),
+    }
+}
+
+// 0 for identical images, 1 for completely different ones
+fn distance(a: &Descriptor, b: &Descriptor) -> f32 {
+    let hash = (a.hash ^ b.hash).count_ones() as f32 / 64.0;
+    let colors: f32 = a
+        .histogram
+        .iter()
+        .zip(&b.histogram)
+        .map(|(x, y)| (x - y).abs())
+        .sum();
+    0.5 * hash + 0.25 * colors
+}
+
+// One line per image: modification time, hash, histogram and path, tab separated
+fn read_index(name: &Path) -> HashMap<String, Entry> {
+    let mut entries = HashMap::new();
+    let contents = match fs::read_to_string(name) {
+        Ok(contents) => contents,
+        Err(_) => return entries,
+    };
+    for line in contents.lines() {
+        let fields: Vec<&str> = line.splitn(4, '\t').collect();
+        if fields.len() != 4 {
+            continue;
+        }
+        let modified = fields[0].parse::<u64>();
+        let hash = u64::from_str_radix(fields[1], 16);
+        let histogram: Result<Vec<f32>, _> = fields[2].split(',').map(str::parse).collect();
+        if let (Ok(modified), Ok(hash), Ok(histogram)) = (modified, hash, histogram) {
+            entries.insert(
+                fields[3].to_string(),
+                Entry {
+                    modified,
+                    descriptor: Descriptor { hash, histogram },
+                },
+            );
+        }
+    }
+    entries
+}
+
+fn write_index(name: &Path, entries: &HashMap<String, Entry>) -> std::io::Result<()> {
+    let mut paths: Vec<&String> = entries.keys().collect();
+    paths.sort();
+    let mut contents = String::new();
+    for path in paths {
+        let entry = &entries[path];
+        let histogram: Vec<String> = entry
+            .descriptor
+            .histogram
+            .iter()
+            .map(|val| val.to_string())
+            .collect();
+        contents.push_str(&format!(
+            "{}\t{:016x}\t{}\t{}\n",
+            entry.modified,
+            entry.descriptor.hash,
+            histogram.join(","),
+            path
+        ));
+    }
+    fs::write(name, contents)
+}
+
+// Brings the index of the directory up to date, only new or modified images are decoded
+fn update_index(dir: &str) -> Result<HashMap<String, Entry>, String> {
+    let index_name = Path::new(dir).join(INDEX_FILE);
+    let mut old = read_index(&index_name);
+    let mut entries = HashMap::new();
+    let mut updated = 0;
+
+    let listing =
+        fs::read_dir(dir).map_err(|error| format!("Could not read {}: {}", dir, error))?;
+    for path in listing.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.extension().is_none_or(|ext| ext != "ppm") {
+            continue;
+        }
+        let modified = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        let key = path.to_string_lossy().into_owned();
+        match old.remove(&key) {
+            Some(entry) if entry.modified == modified => {
+                entries.insert(key, entry);
+            }
+            _ => {
+                let image = parse_ppm(&key).map_err(|error| error.to_string())?;
+                let descriptor = describe(&image);
+                entries.insert(
+                    key,
+                    Entry {
+                        modified,
+                        descriptor,
+                    },
+                );
+                updated += 1;
+            }
+        }
+    }
+
+    write_index(&index_name, &entries)
+        .map_err(|error| format!("Could not write the index: {}", error))?;
+    eprintln!(
+        "{} images indexed, {} updated, {} removed",
+        entries.len(),
+        updated,
+        old.len()
+    );
+    Ok(entries)
+}
+
+pub fn index(dir: &str) -> Result<(), String> {
+    update_index(dir).map(|_| ())
+}
+
+// Prints the closest matches of the directory, most similar first
+pub fn find_similar(query: &str, dir: &str, count: usize) -> Result<(), String> {
+    let entries = update_index(dir)?;
+    let target = describe(&parse_ppm(query).map_err(|error| error.to_string())?);
+    let mut scores: Vec<(f32, &String)> = entries
+        .iter()
+        .map(|(path, entry)| (distance(&target, &entry.descriptor), path))
+        .collect();
+    scores.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (score, path) in scores.iter().take(count) {
+        println!("{:.4}\t{}", score, path);
+    }
+    Ok(())
+}
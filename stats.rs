@@ -0,0 +1,170 @@
+use crate::kernel::{convolve_channel, Border, Kernel};
+use crate::{to_byte, GrayImage, PpmFile};
+
+// JSON has no NaN or infinity, which PFM input can produce
+fn json_number(val: f32) -> String {
+    if val.is_finite() {
+        val.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+pub struct ChannelStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub stddev: f32,
+}
+
+pub struct Stats {
+    pub width: usize,
+    pub height: usize,
+    // red, green and blue
+    pub channels: [ChannelStats; 3],
+    // Shannon entropy of the 8 bit luminance in bits, 0 to 8
+    pub entropy: f32,
+    // variance of the laplacian of the luminance, blurry images score low
+    pub sharpness: f32,
+}
+
+fn channel_stats<F>(image: &PpmFile, channel: F) -> ChannelStats
+where
+    F: Fn(usize) -> f32,
+{
+    let count = image.pixels.len().max(1) as f64;
+    let mut min = f32::INFINITY;
+    let mut max = f32::NEG_INFINITY;
+    let mut sum = 0.0f64;
+    let mut squares = 0.0f64;
+    for i in 0..image.pixels.len() {
+        let val = channel(i);
+        min = min.min(val);
+        max = max.max(val);
+        sum += val as f64;
+        squares += val as f64 * val as f64;
+    }
+    let mean = sum / count;
+    ChannelStats {
+        min,
+        max,
+        mean: mean as f32,
+        stddev: f64::max(squares / count - mean * mean, 0.0).sqrt() as f32,
+    }
+}
+
+pub fn compute(image: &PpmFile) -> Stats {
+    let gray = GrayImage::from_luminance(image);
+
+    let mut bins = [0u32; 256];
+    for &val in &gray.values {
+        bins[to_byte(val) as usize] += 1;
+    }
+    let total = gray.values.len().max(1) as f32;
+    let entropy = bins
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f32 / total;
+            -p * p.log2()
+        })
+        .sum();
+
+    let kernel = Kernel::new(3, 3, vec![0.0, 1.0, 0.0, 1.0, -4.0, 1.0, 0.0, 1.0, 0.0]).unwrap();
+    let laplacian = convolve_channel(
+        &gray.values,
+        gray.width,
+        gray.height,
+        &kernel,
+        Border::Replicate,
+    );
+    let count = laplacian.len().max(1) as f32;
+    let mean = laplacian.iter().sum::<f32>() / count;
+    let sharpness = laplacian
+        .iter()
+        .map(|val| (val - mean) * (val - mean))
+        .sum::<f32>()
+        / count;
+
+    Stats {
+        width: image.width,
+        height: image.height,
+        channels: [
+            channel_stats(image, |i| image.pixels[i].r),
+            channel_stats(image, |i| image.pixels[i].g),
+            channel_stats(image, |i| image.pixels[i].b),
+        ],
+        entropy,
+        sharpness,
+    }
+}
+
+const NAMES: [&str; 3] = ["red", "green", "blue"];
+
+impl Stats {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}x{}\n", self.width, self.height);
+        text.push_str(&format!(
+            "{:<8}{:>8}{:>8}{:>8}{:>8}\n",
+            "channel", "min", "max", "mean", "stddev"
+        ));
+        for (name, channel) in NAMES.iter().zip(&self.channels) {
+            text.push_str(&format!(
+                "{:<8}{:>8.4}{:>8.4}{:>8.4}{:>8.4}\n",
+                name, channel.min, channel.max, channel.mean, channel.stddev
+            ));
+        }
+        text.push_str(&format!("entropy   {:.4} bits\n", self.entropy));
+        text.push_str(&format!("sharpness {:.6}", self.sharpness));
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        let channels: Vec<String> = NAMES
+            .iter()
+            .zip(&self.channels)
+            .map(|(name, channel)| {
+                format!(
+                    "\"{}\": {{\"min\": {}, \"max\": {}, \"mean\": {}, \"stddev\": {}}}",
+                    name,
+                    json_number(channel.min),
+                    json_number(channel.max),
+                    json_number(channel.mean),
+                    json_number(channel.stddev)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"width\": {}, \"height\": {}, {}, \"entropy\": {}, \"sharpness\": {}}}",
+            self.width,
+            self.height,
+            channels.join(", "),
+            json_number(self.entropy),
+            json_number(self.sharpness)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pixel;
+
+    #[test]
+    fn non_finite_values_are_null_in_json() {
+        let mut image = PpmFile::new(2, 1).unwrap();
+        image.pixels[0] = Pixel {
+            r: f32::NAN,
+            g: f32::INFINITY,
+            b: 0.5,
+        };
+        let json = compute(&image).to_json();
+        assert!(!json.contains("NaN") && !json.contains("inf"), "{}", json);
+        assert!(json.contains("\"mean\": null"), "{}", json);
+        assert!(
+            json.contains("\"blue\": {\"min\": 0, \"max\": 0.5"),
+            "{}",
+            json
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{luminance, sobel_magnitude, Pixel, PpmFile};
+
+// Oil painting: the neighbours within the radius are binned by intensity and
+// every pixel becomes the average color of the most populated bin
+pub fn apply_oil_painting(image: &mut PpmFile, radius: usize, levels: usize) {
+    let width = image.width;
+    let height = image.height;
+    let levels = levels.max(1);
+    let pixels = image.pixels.clone();
+    let bins: Vec<usize> = pixels
+        .iter()
+        .map(|p| {
+            usize::min(
+                (luminance(p).clamp(0.0, 1.0) * levels as f32) as usize,
+                levels - 1,
+            )
+        })
+        .collect();
+
+    let mut counts = vec![0usize; levels];
+    let mut sums = vec![(0.0f32, 0.0f32, 0.0f32); levels];
+    for y in 0..height {
+        for x in 0..width {
+            counts.iter_mut().for_each(|count| *count = 0);
+            sums.iter_mut().for_each(|sum| *sum = (0.0, 0.0, 0.0));
+            for ny in y.saturating_sub(radius)..usize::min(y + radius + 1, height) {
+                for nx in x.saturating_sub(radius)..usize::min(x + radius + 1, width) {
+                    let i = ny * width + nx;
+                    let bin = bins[i];
+                    counts[bin] += 1;
+                    sums[bin].0 += pixels[i].r;
+                    sums[bin].1 += pixels[i].g;
+                    sums[bin].2 += pixels[i].b;
+                }
+            }
+
+            let mut mode = 0;
+            for bin in 1..levels {
+                if counts[bin] > counts[mode] {
+                    mode = bin;
+                }
+            }
+            let count = counts[mode] as f32;
+            image.pixels[y * width + x] = Pixel {
+                r: sums[mode].0 / count,
+                g: sums[mode].1 / count,
+                b: sums[mode].2 / count,
+            };
+        }
+    }
+}
+
+// Kuwahara: of the four (radius + 1) sized quadrants having the pixel as a
+// corner, the one with the lowest luminance variance gives its mean color
+pub fn apply_kuwahara(image: &mut PpmFile, radius: usize) {
+    let width = image.width as isize;
+    let height = image.height as isize;
+    let radius = radius as isize;
+    let pixels = image.pixels.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut best: Option<(f32, Pixel)> = None;
+            for &(dx, dy) in &[(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                let (x0, x1) = if dx < 0 {
+                    (x - radius, x)
+                } else {
+                    (x, x + radius)
+                };
+                let (y0, y1) = if dy < 0 {
+                    (y - radius, y)
+                } else {
+                    (y, y + radius)
+                };
+                let mut sum = Pixel {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                };
+                let mut lum_sum = 0.0;
+                let mut lum_sq = 0.0;
+                let mut count = 0.0;
+                for qy in y0.max(0)..=y1.min(height - 1) {
+                    for qx in x0.max(0)..=x1.min(width - 1) {
+                        let p = &pixels[(qy * width + qx) as usize];
+                        let lum = luminance(p);
+                        sum.r += p.r;
+                        sum.g += p.g;
+                        sum.b += p.b;
+                        lum_sum += lum;
+                        lum_sq += lum * lum;
+                        count += 1.0;
+                    }
+                }
+                let mean = lum_sum / count;
+                let variance = lum_sq / count - mean * mean;
+                if best.as_ref().is_none_or(|(lowest, _)| variance < *lowest) {
+                    let mean = Pixel {
+                        r: sum.r / count,
+                        g: sum.g / count,
+                        b: sum.b / count,
+                    };
+                    best = Some((variance, mean));
+                }
+            }
+            if let Some((_, mean)) = best {
+                image.pixels[(y * width + x) as usize] = mean;
+            }
+        }
+    }
+}
+
+// Kuwahara smoothing, posterization to the number of levels per channel and
+// black outlines where the sobel magnitude of the smoothed image reaches the
+// edge threshold
+pub fn apply_cartoon(image: &mut PpmFile, radius: usize, levels: usize, edge: f32) {
+    apply_kuwahara(image, radius);
+    let edges = sobel_magnitude(image);
+    let steps = levels.max(2) as f32 - 1.0;
+    let posterize = |val: f32| (val.clamp(0.0, 1.0) * steps).round() / steps;
+    for (pixel, magnitude) in image.pixels.iter_mut().zip(edges.values) {
+        if magnitude >= edge {
+            pixel.r = 0.0;
+            pixel.g = 0.0;
+            pixel.b = 0.0;
+        } else {
+            pixel.r = posterize(pixel.r);
+            pixel.g = posterize(pixel.g);
+            pixel.b = posterize(pixel.b);
+        }
+    }
+}
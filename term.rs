@@ -0,0 +1,84 @@
+use std::fmt::Write;
+
+use crate::{to_byte, Pixel, PpmFile};
+
+// Mean of the pixels in the box from (x0, y0) up to (x1, y1), rounded outwards
+fn box_mean(image: &PpmFile, x0: f32, y0: f32, x1: f32, y1: f32) -> Pixel {
+    let xs = x0 as usize..(x1.ceil() as usize).clamp(x0 as usize + 1, image.width);
+    let ys = y0 as usize..(y1.ceil() as usize).clamp(y0 as usize + 1, image.height);
+    let count = (xs.len() * ys.len()) as f32;
+    let mut sum = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+    };
+    for y in ys {
+        for pixel in &image.pixels[y * image.width + xs.start..y * image.width + xs.end] {
+            sum.r += pixel.r;
+            sum.g += pixel.g;
+            sum.b += pixel.b;
+        }
+    }
+    Pixel {
+        r: sum.r / count,
+        g: sum.g / count,
+        b: sum.b / count,
+    }
+}
+
+// The image shrunk to the given number of columns as ANSI truecolor text. Every
+// character is an upper half block showing two pixels, the top one in the
+// foreground color and the bottom one in the background color, which keeps
+// the pixels about square in a usual terminal font.
+pub fn render(image: &PpmFile, columns: usize) -> String {
+    let columns = columns.clamp(1, image.width.max(1));
+    let cell = image.width as f32 / columns as f32;
+    let half_rows = ((image.height as f32 / cell).round() as usize).max(1);
+    let cell_height = image.height as f32 / half_rows as f32;
+
+    let mut text = String::new();
+    if image.pixels.is_empty() {
+        return text;
+    }
+    for row in (0..half_rows).step_by(2) {
+        for column in 0..columns {
+            let x0 = column as f32 * cell;
+            let x1 = x0 + cell;
+            let top = box_mean(
+                image,
+                x0,
+                row as f32 * cell_height,
+                x1,
+                (row + 1) as f32 * cell_height,
+            );
+            write!(
+                text,
+                "\x1b[38;2;{};{};{}m",
+                to_byte(top.r),
+                to_byte(top.g),
+                to_byte(top.b)
+            )
+            .unwrap();
+            if row + 1 < half_rows {
+                let bottom = box_mean(
+                    image,
+                    x0,
+                    (row + 1) as f32 * cell_height,
+                    x1,
+                    (row + 2) as f32 * cell_height,
+                );
+                write!(
+                    text,
+                    "\x1b[48;2;{};{};{}m",
+                    to_byte(bottom.r),
+                    to_byte(bottom.g),
+                    to_byte(bottom.b)
+                )
+                .unwrap();
+            }
+            text.push('▀');
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    text
+}
@@ -0,0 +1,156 @@
+use crate::rng::Rng;
+use crate::{Pixel, PpmFile};
+
+// Gradients picked by the lattice hash of perlin and simplex noise
+const GRADIENTS: [(f32, f32); 8] = [
+    (1.0, 1.0),
+    (-1.0, 1.0),
+    (1.0, -1.0),
+    (-1.0, -1.0),
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+];
+
+#[derive(Clone, Copy)]
+pub enum Noise {
+    Value,
+    Perlin,
+    Simplex,
+}
+
+impl Noise {
+    pub fn parse(name: &str) -> Option<Noise> {
+        match name {
+            "value" => Some(Noise::Value),
+            "perlin" => Some(Noise::Perlin),
+            "simplex" => Some(Noise::Simplex),
+            _ => None,
+        }
+    }
+}
+
+pub struct NoiseParams {
+    pub seed: u64,
+    // lattice cells across the width of the image for the first octave
+    pub frequency: f32,
+    // every octave doubles the frequency and halves the amplitude
+    pub octaves: usize,
+}
+
+// Random bits of a lattice point, the same for the same seed
+fn hash(seed: u64, x: i64, y: i64) -> u64 {
+    let point = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    Rng::new(seed ^ point).next_u64()
+}
+
+fn gradient(seed: u64, x: i64, y: i64) -> (f32, f32) {
+    GRADIENTS[(hash(seed, x, y) >> 61) as usize]
+}
+
+// Smooth step with zero first and second derivatives at 0 and 1
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Random values at the lattice points, interpolated in between. From -1 to 1.
+fn value(seed: u64, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+    let (tx, ty) = (fade(x - x0 as f32), fade(y - y0 as f32));
+    let corner =
+        |dx: i64, dy: i64| (hash(seed, x0 + dx, y0 + dy) >> 40) as f32 / (1 << 23) as f32 - 1.0;
+    lerp(
+        lerp(corner(0, 0), corner(1, 0), tx),
+        lerp(corner(0, 1), corner(1, 1), tx),
+        ty,
+    )
+}
+
+// Ken Perlin's improved gradient noise, about -1 to 1
+fn perlin(seed: u64, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let corner = |dx: i64, dy: i64| {
+        let (gx, gy) = gradient(seed, x0 + dx, y0 + dy);
+        gx * (fx - dx as f32) + gy * (fy - dy as f32)
+    };
+    let (tx, ty) = (fade(fx), fade(fy));
+    lerp(
+        lerp(corner(0, 0), corner(1, 0), tx),
+        lerp(corner(0, 1), corner(1, 1), tx),
+        ty,
+    )
+}
+
+// Gradient noise on a lattice of triangles, fewer directional artifacts than
+// perlin noise. About -1 to 1.
+fn simplex(seed: u64, x: f32, y: f32) -> f32 {
+    let skew = 0.5 * (3.0f32.sqrt() - 1.0);
+    let unskew = (3.0 - 3.0f32.sqrt()) / 6.0;
+
+    let s = (x + y) * skew;
+    let (i, j) = ((x + s).floor() as i64, (y + s).floor() as i64);
+    let t = (i + j) as f32 * unskew;
+    let (x0, y0) = (x - (i as f32 - t), y - (j as f32 - t));
+    // the triangle the point is in
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let corners = [
+        (0, 0, x0, y0),
+        (i1, j1, x0 - i1 as f32 + unskew, y0 - j1 as f32 + unskew),
+        (1, 1, x0 - 1.0 + 2.0 * unskew, y0 - 1.0 + 2.0 * unskew),
+    ];
+
+    let mut sum = 0.0;
+    for &(di, dj, cx, cy) in &corners {
+        let falloff = 0.5 - cx * cx - cy * cy;
+        if falloff > 0.0 {
+            let (gx, gy) = gradient(seed, i + di, j + dj);
+            sum += falloff.powi(4) * (gx * cx + gy * cy);
+        }
+    }
+    70.0 * sum
+}
+
+// Gray fractal noise from 0 to 1
+pub fn generate(noise: Noise, width: usize, height: usize, params: &NoiseParams) -> PpmFile {
+    let sample = match noise {
+        Noise::Value => value,
+        Noise::Perlin => perlin,
+        Noise::Simplex => simplex,
+    };
+    let scale = params.frequency / width.max(1) as f32;
+    let octaves = params.octaves.max(1);
+    let total: f32 = (0..octaves).map(|octave| 0.5f32.powi(octave as i32)).sum();
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            for octave in 0..octaves {
+                let zoom = scale * 2f32.powi(octave as i32);
+                let seed = params.seed.wrapping_add(octave as u64);
+                sum += sample(seed, x as f32 * zoom, y as f32 * zoom) * 0.5f32.powi(octave as i32);
+            }
+            let val = (0.5 + 0.5 * sum / total).clamp(0.0, 1.0);
+            pixels.push(Pixel {
+                r: val,
+                g: val,
+                b: val,
+            });
+        }
+    }
+    PpmFile {
+        width,
+        height,
+        max_val: 255,
+        comments: Vec::new(),
+        alpha: None,
+        pixels,
+    }
+}
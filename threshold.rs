@@ -0,0 +1,128 @@
+use crate::kernel::{convolve_channel, Border, Kernel};
+use crate::{luminance, PpmFile};
+
+// Pixels with a luminance at or above the cutoff turn white, the rest black
+pub fn apply_threshold(image: &mut PpmFile, cutoff: f32) {
+    for pixel in &mut image.pixels {
+        let val = if luminance(pixel) >= cutoff { 1.0 } else { 0.0 };
+        pixel.r = val;
+        pixel.g = val;
+        pixel.b = val;
+    }
+}
+
+// The cutoff maximizing the between-class variance of the luminance histogram
+pub fn otsu_threshold(image: &PpmFile) -> f32 {
+    let mut histogram = [0u64; 256];
+    for pixel in &image.pixels {
+        histogram[(luminance(pixel).clamp(0.0, 1.0) * 255.0).round() as usize] += 1;
+    }
+
+    let total = image.pixels.len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(level, &count)| level as f64 * count as f64)
+        .sum();
+
+    let mut best_level = 0;
+    let mut best_variance = 0.0;
+    let mut background = 0.0;
+    let mut background_sum = 0.0;
+    for (level, &count) in histogram.iter().enumerate() {
+        background += count as f64;
+        if background == 0.0 {
+            continue;
+        }
+        let foreground = total - background;
+        if foreground == 0.0 {
+            break;
+        }
+        background_sum += level as f64 * count as f64;
+        let mean_back = background_sum / background;
+        let mean_fore = (sum - background_sum) / foreground;
+        let variance = background * foreground * (mean_back - mean_fore) * (mean_back - mean_fore);
+        if variance > best_variance {
+            best_variance = variance;
+            best_level = level;
+        }
+    }
+    // the best level still belongs to the background
+    (best_level as f32 + 0.5) / 255.0
+}
+
+pub enum LocalMean {
+    Mean,
+    Gaussian,
+}
+
+// Compares every pixel against the (mean or gaussian weighted) luminance of its
+// window*window neighbourhood minus the offset. Only the part of the window
+// inside of the image is taken into account.
+pub fn apply_adaptive_threshold(
+    image: &mut PpmFile,
+    method: LocalMean,
+    window: usize,
+    offset: f32,
+) -> Result<(), String> {
+    if window.is_multiple_of(2) {
+        return Err(format!("The window should be odd but {} given!", window));
+    }
+    let width = image.width;
+    let height = image.height;
+    let lums: Vec<f32> = image.pixels.iter().map(luminance).collect();
+
+    let local: Vec<f32> = match method {
+        LocalMean::Mean => {
+            // summed area table with an extra zero row and column
+            let mut sums = vec![0.0f64; (width + 1) * (height + 1)];
+            for y in 0..height {
+                for x in 0..width {
+                    sums[(y + 1) * (width + 1) + x + 1] = lums[y * width + x] as f64
+                        + sums[y * (width + 1) + x + 1]
+                        + sums[(y + 1) * (width + 1) + x]
+                        - sums[y * (width + 1) + x];
+                }
+            }
+            let radius = window / 2;
+            let mut local = Vec::with_capacity(lums.len());
+            for y in 0..height {
+                let top = y.saturating_sub(radius);
+                let bottom = usize::min(y + radius + 1, height);
+                for x in 0..width {
+                    let left = x.saturating_sub(radius);
+                    let right = usize::min(x + radius + 1, width);
+                    let sum = sums[bottom * (width + 1) + right]
+                        - sums[top * (width + 1) + right]
+                        - sums[bottom * (width + 1) + left]
+                        + sums[top * (width + 1) + left];
+                    local.push((sum / ((bottom - top) * (right - left)) as f64) as f32);
+                }
+            }
+            local
+        }
+        LocalMean::Gaussian => {
+            // same sigma for a given window as OpenCV
+            let sigma = 0.3 * ((window as f32 - 1.0) * 0.5 - 1.0) + 0.8;
+            let kernel = Kernel::gaussian(window, sigma)?;
+            let weighted = convolve_channel(&lums, width, height, &kernel, Border::Constant(0.0));
+            // the weights that fell inside of the image
+            let coverage = convolve_channel(
+                &vec![1.0; lums.len()],
+                width,
+                height,
+                &kernel,
+                Border::Constant(0.0),
+            );
+            weighted.iter().zip(coverage).map(|(w, c)| w / c).collect()
+        }
+    };
+
+    for ((pixel, lum), mean) in image.pixels.iter_mut().zip(lums).zip(local) {
+        let val = if lum > mean - offset { 1.0 } else { 0.0 };
+        pixel.r = val;
+        pixel.g = val;
+        pixel.b = val;
+    }
+    Ok(())
+}
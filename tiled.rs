@@ -0,0 +1,287 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::sync::atomic::Ordering;
+
+use crate::kernel::{Border, Kernel};
+use crate::morphology::StructuringElement;
+use crate::packed::PackedImage;
+use crate::{
+    apply_step, decoded_pixel_count, encode_output, header_comments, is_space, parse_params,
+    ppm_header, save_atomic, PpmError, ATOMIC_WRITES, SIDE_EFFECTS,
+};
+
+// Steps only looking at the pixel itself
+const PIXELWISE: &[&str] = &[
+    "linear",
+    "gray",
+    "gray-mode",
+    "dither-output",
+    "invert",
+    "solarize",
+    "sepia",
+    "levels",
+    "levels-r",
+    "levels-g",
+    "levels-b",
+    "hue-shift",
+    "saturation",
+    "value",
+    "to-space",
+    "from-space",
+    "temperature",
+    "tint",
+    "channel",
+    "swap",
+    "colormap",
+    "tonemap",
+    "chroma-key",
+    "border",
+    "threads",
+];
+
+// Steps whose value may be given as the next argument
+const VALUED: &[&str] = &[
+    "gray-mode",
+    "kernel",
+    "kernel-file",
+    "colormap",
+    "temperature",
+    "tint",
+    "comment",
+    "to-space",
+    "from-space",
+];
+
+// Rows above and below a pixel the step reads to compute it, an error for
+// steps that need the whole image at once
+fn margin(name: &str, value: Option<&str>) -> Result<usize, String> {
+    if SIDE_EFFECTS.contains(&name) {
+        return Err(format!(
+            "{} writes files or reports and would run once per strip!",
+            name
+        ));
+    }
+    // the strips would wrap around their own edges instead of the image's
+    if name == "border" && matches!(value.map(Border::parse), Some(Ok(Border::Wrap))) {
+        return Err("border=wrap can't run tiled!".to_string());
+    }
+    if PIXELWISE.contains(&name) {
+        return Ok(0);
+    }
+    let radius = match name {
+        "gauss" | "laplace" => 1,
+        "log" => 2,
+        "sobel" if !value.is_some_and(|value| value.starts_with("normalized")) => 1,
+        // a fixed cutoff, Otsu's picks it from the whole histogram
+        "threshold" if value.is_some_and(|value| value != "otsu") => 0,
+        "kernel" | "kernel-file" => {
+            let spec = match (name, value) {
+                ("kernel", Some(spec)) => spec.to_string(),
+                ("kernel-file", Some(file)) => fs::read_to_string(file)
+                    .map_err(|error| format!("Could not read kernel file: {}", error))?,
+                _ => return Err(format!("{} expects a value!", name)),
+            };
+            let kernel = Kernel::parse(&spec)?;
+            usize::max(kernel.width, kernel.height) / 2
+        }
+        "motion" => {
+            let params = parse_params(name, value, &[9.0, 0.0]);
+            (params[0] / 2.0).ceil() as usize
+        }
+        "dog" => {
+            let params = parse_params(name, value, &[1.0, 1.6, 4.0]);
+            (3.0 * f32::max(params[0], params[1])).ceil() as usize
+        }
+        "highpass" => (3.0 * parse_params(name, value, &[3.0, 0.5])[0]).ceil() as usize,
+        "adaptive-mean" | "adaptive-gauss" => {
+            parse_params(name, value, &[15.0, 0.02])[0] as usize / 2
+        }
+        "erode" | "dilate" => {
+            let element = StructuringElement::parse(value.unwrap_or("square,1"))?;
+            usize::max(element.width, element.height) / 2
+        }
+        "kuwahara" => parse_params(name, value, &[2.0])[0] as usize,
+        "oil" => parse_params(name, value, &[3.0, 20.0])[0] as usize,
+        "cartoon" => parse_params(name, value, &[3.0, 6.0, 0.4])[0] as usize + 1,
+        "nlmeans" => {
+            let params = parse_params(name, value, &[0.1, 1.0, 5.0]);
+            params[1] as usize + params[2] as usize
+        }
+        _ => {
+            return Err(format!(
+                "{} needs the whole image and can't run tiled!",
+                name
+            ))
+        }
+    };
+    Ok(radius)
+}
+
+// Next whitespace separated header field, skipping comments. Every byte read
+// is kept in the header.
+fn header_token<R: BufRead>(reader: &mut R, header: &mut Vec<u8>) -> Result<String, PpmError> {
+    let mut token = String::new();
+    let mut byte = [0u8];
+    loop {
+        if reader.read(&mut byte).map_err(|_| PpmError::TooSmall)? == 0 {
+            return Err(PpmError::TooSmall);
+        }
+        header.push(byte[0]);
+        match byte[0] {
+            b'#' => {
+                reader
+                    .read_until(b'\n', header)
+                    .map_err(|_| PpmError::TooSmall)?;
+                if !token.is_empty() {
+                    return Ok(token);
+                }
+            }
+            b if is_space(b) && token.is_empty() => {}
+            // the single whitespace after the field is consumed with it
+            b if is_space(b) => return Ok(token),
+            val => token.push(val as char),
+        }
+    }
+}
+
+// Reads the P6 header, leaving the reader at the first pixel. Returns the
+// size and the comments.
+pub fn read_header<R: BufRead>(reader: &mut R) -> Result<(usize, usize, Vec<String>), PpmError> {
+    let mut header = Vec::new();
+    let magic = header_token(reader, &mut header)?;
+    if magic != "P6" {
+        return Err(PpmError::Malformed(format!(
+            "Only P6 images can be streamed but {} found",
+            magic
+        )));
+    }
+    let mut field = |what: &str| -> Result<usize, PpmError> {
+        let token = header_token(reader, &mut header)?;
+        token
+            .parse::<usize>()
+            .map_err(|error| PpmError::Malformed(format!("Bad {} {}: {}", what, token, error)))
+    };
+    let width = field("width")?;
+    let height = field("height")?;
+    let max_val = field("max color value")?;
+    if max_val != 255 {
+        return Err(PpmError::Malformed(format!(
+            "Maximum color value should be 255 but {} found",
+            max_val
+        )));
+    }
+    Ok((width, height, header_comments(&header)))
+}
+
+// Streams the image through the steps a strip of rows at a time, every strip
+// being read with enough rows above and below for the neighbourhood filters.
+// Only the strip and its margins are ever in memory.
+pub fn run(file: &str, output: &str, rows: usize, args: &[String]) -> Result<(), Box<dyn Error>> {
+    // every step with its value, `--kernel <spec>` becomes `kernel=<spec>`
+    let mut steps = Vec::new();
+    let mut context = 0;
+    let mut added = Vec::new();
+    let mut keep_comments = true;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.trim_start_matches("--");
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None if VALUED.contains(&arg) => (arg, args.next().cloned()),
+            None => (arg, None),
+        };
+        // these only touch the header, which is written once
+        match name {
+            "no-atomic" => {
+                ATOMIC_WRITES.store(false, Ordering::Relaxed);
+                continue;
+            }
+            "comment" => {
+                added.push(value.ok_or("comment expects a value!")?);
+                continue;
+            }
+            "no-comments" => {
+                keep_comments = false;
+                added.clear();
+                continue;
+            }
+            _ => {}
+        }
+        context += margin(name, value.as_deref())?;
+        steps.push(match value {
+            Some(value) => format!("{}={}", name, value),
+            None => name.to_string(),
+        });
+    }
+    let rows = rows.max(1);
+
+    let mut reader = BufReader::new(File::open(file)?);
+    let (width, height, mut comments) = read_header(&mut reader)?;
+    if !keep_comments {
+        comments.clear();
+    }
+    for text in added {
+        comments.extend(text.lines().map(|line| line.trim_end().to_string()));
+    }
+    let len = decoded_pixel_count(width, height)?
+        .checked_mul(3)
+        .ok_or(PpmError::DimensionOverflow { width, height })?;
+    // checked up front so a lying header can't make the rows below huge
+    let found = reader
+        .get_ref()
+        .metadata()?
+        .len()
+        .saturating_sub(reader.stream_position()?) as usize;
+    if found < len {
+        return Err(PpmError::Truncated {
+            expected: len,
+            found,
+        }
+        .into());
+    }
+    let row_len = width * 3;
+    let none: Vec<String> = Vec::new();
+
+    save_atomic(output, |path| {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(ppm_header(width, height, &comments).as_bytes())?;
+
+        // rows top..bottom of the input are in memory
+        let mut window: Vec<[u8; 3]> = Vec::new();
+        let mut top = 0;
+        let mut bottom = 0;
+        let mut row = vec![0u8; row_len];
+        for start in (0..height).step_by(rows) {
+            let end = usize::min(start + rows, height);
+            let first = start.saturating_sub(context);
+            let last = usize::min(end + context, height);
+            window.drain(..(first - top) * width);
+            top = first;
+            while bottom < last {
+                reader.read_exact(&mut row)?;
+                window.extend(row.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]));
+                bottom += 1;
+            }
+
+            let mut strip = PackedImage {
+                width,
+                height: last - first,
+                pixels: window.clone(),
+                alpha: None,
+                comments: Vec::new(),
+            }
+            .unpack();
+            for step in &steps {
+                apply_step(&mut strip, step, &mut none.iter())
+                    .map_err(|error| std::io::Error::other(error.to_string()))?;
+            }
+            let strip = encode_output(&strip, start as u64);
+            out.write_all(
+                strip.pixels[(start - first) * width..(end - first) * width].as_flattened(),
+            )?;
+        }
+        out.into_inner()?.sync_all()
+    })?;
+    Ok(())
+}
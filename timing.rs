@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::time::Instant;
+
+use crate::colormap::Colormap;
+use crate::{save_ppm, PpmFile, SETTINGS, SIDE_EFFECTS};
+
+const TILE: usize = 64;
+// context around every tile so neighbourhood filters see realistic input
+const MARGIN: usize = 16;
+
+// Only steps that do nothing but change the image can be timed per tile
+pub fn check_step(step: &str) -> Result<(), String> {
+    let name = step
+        .trim_start_matches("--")
+        .split('=')
+        .next()
+        .unwrap_or("");
+    if SIDE_EFFECTS.contains(&name) || SETTINGS.contains(&name) || name == "[" || name == "]" {
+        return Err(format!(
+            "timing can only measure steps without side effects but {} given!",
+            name
+        ));
+    }
+    Ok(())
+}
+
+// Runs the step separately on every tile of the image (plus a margin) and
+// writes the time each one took as a magma heatmap. The image itself is left
+// untouched, the caller still has to apply the step to it.
+pub fn export_heatmap<F>(image: &PpmFile, name: &str, mut step: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(&mut PpmFile) -> Result<bool, Box<dyn Error>>,
+{
+    let mut tiles: Vec<(usize, usize, usize, usize, f32)> = Vec::new();
+    for y0 in (0..image.height).step_by(TILE) {
+        for x0 in (0..image.width).step_by(TILE) {
+            let x1 = usize::min(x0 + TILE, image.width);
+            let y1 = usize::min(y0 + TILE, image.height);
+            let left = x0.saturating_sub(MARGIN);
+            let top = y0.saturating_sub(MARGIN);
+            let mut tile = image.crop(
+                left,
+                top,
+                usize::min(x1 + MARGIN, image.width) - left,
+                usize::min(y1 + MARGIN, image.height) - top,
+            );
+
+            let start = Instant::now();
+            step(&mut tile)?;
+            tiles.push((x0, y0, x1, y1, start.elapsed().as_secs_f32()));
+        }
+    }
+
+    let slowest = tiles.iter().map(|tile| tile.4).fold(0.0, f32::max);
+    let colormap = Colormap::load("magma")?;
+    let mut heatmap = PpmFile::new(image.width, image.height)?;
+    for (x0, y0, x1, y1, seconds) in tiles {
+        let color = colormap.sample(if slowest > 0.0 {
+            seconds / slowest
+        } else {
+            0.0
+        });
+        for y in y0..y1 {
+            for x in x0..x1 {
+                heatmap.pixels[y * heatmap.width + x] = color.clone();
+            }
+        }
+    }
+    eprintln!("Slowest tile took {:.3} ms", slowest * 1000.0);
+    save_ppm(&heatmap, name)?;
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use crate::{luminance, PpmFile};
+
+pub enum ToneMap {
+    // L / (1 + L) on the luminance, keeping the hue and saturation
+    Reinhard,
+    // ACES filmic curve fitted by Krzysztof Narkowicz, per channel
+    Filmic,
+}
+
+fn filmic(val: f32) -> f32 {
+    (val * (2.51 * val + 0.03)) / (val * (2.43 * val + 0.59) + 0.14)
+}
+
+// Compresses HDR values into 0-1 after multiplying them by the exposure
+pub fn apply_tone_map(image: &mut PpmFile, method: ToneMap, exposure: f32) {
+    for pixel in &mut image.pixels {
+        pixel.r = f32::max(pixel.r * exposure, 0.0);
+        pixel.g = f32::max(pixel.g * exposure, 0.0);
+        pixel.b = f32::max(pixel.b * exposure, 0.0);
+        match method {
+            ToneMap::Reinhard => {
+                let lum = luminance(pixel);
+                if lum > 0.0 {
+                    let scale = 1.0 / (1.0 + lum);
+                    pixel.r *= scale;
+                    pixel.g *= scale;
+                    pixel.b *= scale;
+                }
+            }
+            ToneMap::Filmic => {
+                pixel.r = filmic(pixel.r);
+                pixel.g = filmic(pixel.g);
+                pixel.b = filmic(pixel.b);
+            }
+        }
+        pixel.r = pixel.r.clamp(0.0, 1.0);
+        pixel.g = pixel.g.clamp(0.0, 1.0);
+        pixel.b = pixel.b.clamp(0.0, 1.0);
+    }
+}